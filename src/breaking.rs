@@ -0,0 +1,610 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use crate::proto::{self, Enum, Label, Message, Position, ProtoFile, Range};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    /// Existing clients will misinterpret or fail to decode messages, or calls will fail.
+    Wire,
+    /// Encoded data is unaffected but code generated from the schema will no longer compile.
+    Source,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Wire => write!(f, "wire"),
+            Kind::Source => write!(f, "source"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakingChange {
+    pub kind: Kind,
+    /// The message, enum or service affected, e.g. `message org.repo.v1.Foo`.
+    pub subject: String,
+    pub description: String,
+    /// File containing the subject in the newer schema, or the older one if it was deleted.
+    pub file: PathBuf,
+    pub position: Option<Position>,
+}
+
+impl Display for BreakingChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} breaking change in {}: {}",
+            self.kind, self.subject, self.description
+        )
+    }
+}
+
+struct Located<'a, T> {
+    file: &'a PathBuf,
+    item: &'a T,
+}
+
+/// Every message, enum and service in a set of proto files, keyed by fully qualified name.
+#[derive(Default)]
+pub struct Schema {
+    files: Vec<(PathBuf, ProtoFile)>,
+}
+
+impl Schema {
+    /// Parses the given files, skipping any that are not valid protobuf as there is nothing
+    /// meaningful to compare them against.
+    pub fn parse(files: Vec<(PathBuf, String)>) -> Schema {
+        let files = files
+            .into_iter()
+            .filter_map(|(path, contents)| match proto::parse(&contents) {
+                Ok(file) => Some((path, file)),
+                Err(error) => {
+                    log::warn!(
+                        "Skipping {} when checking for breaking changes: {}",
+                        path.display(),
+                        error
+                    );
+                    None
+                }
+            })
+            .collect();
+        Schema { files }
+    }
+
+    fn index(&self) -> Index<'_> {
+        let mut index = Index::default();
+        for (path, file) in self.files.iter() {
+            let package = file
+                .package
+                .as_ref()
+                .map(|p| p.name.clone())
+                .unwrap_or_default();
+            for message in file.messages.iter() {
+                index.add_message(path, &package, message);
+            }
+            for enumeration in file.enums.iter() {
+                index.enums.insert(
+                    qualify(&package, &enumeration.name),
+                    Located {
+                        file: path,
+                        item: enumeration,
+                    },
+                );
+            }
+            for service in file.services.iter() {
+                index.services.insert(
+                    qualify(&package, &service.name),
+                    Located {
+                        file: path,
+                        item: service,
+                    },
+                );
+            }
+        }
+        index
+    }
+}
+
+#[derive(Default)]
+struct Index<'a> {
+    messages: BTreeMap<String, Located<'a, Message>>,
+    enums: BTreeMap<String, Located<'a, Enum>>,
+    services: BTreeMap<String, Located<'a, proto::Service>>,
+}
+
+impl<'a> Index<'a> {
+    fn add_message(&mut self, file: &'a PathBuf, scope: &str, message: &'a Message) {
+        let name = qualify(scope, &message.name);
+        for nested in message.messages.iter() {
+            self.add_message(file, &name, nested);
+        }
+        for enumeration in message.enums.iter() {
+            self.enums.insert(
+                qualify(&name, &enumeration.name),
+                Located {
+                    file,
+                    item: enumeration,
+                },
+            );
+        }
+        self.messages.insert(
+            name,
+            Located {
+                file,
+                item: message,
+            },
+        );
+    }
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn is_reserved(ranges: &[Range], number: i64) -> bool {
+    ranges
+        .iter()
+        .any(|(start, end)| *start <= number && number <= *end)
+}
+
+/// Type references may be relative or fully qualified, so two references are treated as the
+/// same type when one is a suffix of the other on a package boundary.
+fn same_type(old: &str, new: &str) -> bool {
+    let old = old.trim_start_matches('.');
+    let new = new.trim_start_matches('.');
+    old == new || old.ends_with(&format!(".{}", new)) || new.ends_with(&format!(".{}", old))
+}
+
+struct Changes {
+    changes: Vec<BreakingChange>,
+}
+
+impl Changes {
+    fn push<T>(
+        &mut self,
+        kind: Kind,
+        subject: &str,
+        located: &Located<T>,
+        position: Option<Position>,
+        description: String,
+    ) {
+        self.changes.push(BreakingChange {
+            kind,
+            subject: subject.to_owned(),
+            description,
+            file: located.file.clone(),
+            position,
+        });
+    }
+}
+
+/// Lists the changes between two versions of a schema that would break existing consumers.
+pub fn compare(old: &Schema, new: &Schema) -> Vec<BreakingChange> {
+    let old = old.index();
+    let new = new.index();
+    let mut changes = Changes {
+        changes: Vec::new(),
+    };
+
+    for (name, old_message) in old.messages.iter() {
+        let subject = format!("message {}", name);
+        match new.messages.get(name) {
+            Some(new_message) => compare_messages(&mut changes, &subject, old_message, new_message),
+            None => changes.push(
+                Kind::Source,
+                &subject,
+                old_message,
+                Some(old_message.item.position),
+                "message was deleted".to_owned(),
+            ),
+        }
+    }
+
+    for (name, old_enum) in old.enums.iter() {
+        let subject = format!("enum {}", name);
+        match new.enums.get(name) {
+            Some(new_enum) => compare_enums(&mut changes, &subject, old_enum, new_enum),
+            None => changes.push(
+                Kind::Source,
+                &subject,
+                old_enum,
+                Some(old_enum.item.position),
+                "enum was deleted".to_owned(),
+            ),
+        }
+    }
+
+    for (name, old_service) in old.services.iter() {
+        let subject = format!("service {}", name);
+        match new.services.get(name) {
+            Some(new_service) => compare_services(&mut changes, &subject, old_service, new_service),
+            None => changes.push(
+                Kind::Wire,
+                &subject,
+                old_service,
+                Some(old_service.item.position),
+                "service was deleted".to_owned(),
+            ),
+        }
+    }
+
+    let mut changes = changes.changes;
+    changes.sort_by(|a, b| a.subject.cmp(&b.subject).then(a.kind.cmp(&b.kind)));
+    changes
+}
+
+fn compare_messages(
+    changes: &mut Changes,
+    subject: &str,
+    old: &Located<Message>,
+    new: &Located<Message>,
+) {
+    for old_field in old.item.fields.iter() {
+        let new_field = new
+            .item
+            .fields
+            .iter()
+            .find(|f| f.number == old_field.number);
+
+        let new_field = match new_field {
+            Some(field) => field,
+            None => {
+                if is_reserved(&new.item.reserved_ranges, old_field.number) {
+                    changes.push(
+                        Kind::Source,
+                        subject,
+                        new,
+                        Some(new.item.position),
+                        format!(
+                            "field {} ({}) was deleted",
+                            old_field.number, old_field.name
+                        ),
+                    );
+                } else {
+                    changes.push(
+                        Kind::Wire,
+                        subject,
+                        new,
+                        Some(new.item.position),
+                        format!(
+                            "field {} ({}) was deleted without reserving its number",
+                            old_field.number, old_field.name
+                        ),
+                    );
+                }
+                continue;
+            }
+        };
+
+        let position = Some(new_field.position);
+
+        if !same_type(&old_field.type_name, &new_field.type_name) {
+            changes.push(
+                Kind::Wire,
+                subject,
+                new,
+                position,
+                format!(
+                    "field {} ({}) changed type from {} to {}",
+                    old_field.number, old_field.name, old_field.type_name, new_field.type_name
+                ),
+            );
+        }
+
+        if (old_field.label == Label::Repeated) != (new_field.label == Label::Repeated) {
+            changes.push(
+                Kind::Wire,
+                subject,
+                new,
+                position,
+                format!(
+                    "field {} ({}) changed between repeated and singular",
+                    old_field.number, old_field.name
+                ),
+            );
+        } else if old_field.label != new_field.label {
+            changes.push(
+                Kind::Source,
+                subject,
+                new,
+                position,
+                format!(
+                    "field {} ({}) changed label from {:?} to {:?}",
+                    old_field.number, old_field.name, old_field.label, new_field.label
+                ),
+            );
+        }
+
+        if old_field.oneof != new_field.oneof {
+            changes.push(
+                Kind::Wire,
+                subject,
+                new,
+                position,
+                format!(
+                    "field {} ({}) moved from oneof {} to oneof {}",
+                    old_field.number,
+                    old_field.name,
+                    old_field.oneof.as_deref().unwrap_or("(none)"),
+                    new_field.oneof.as_deref().unwrap_or("(none)")
+                ),
+            );
+        }
+
+        if old_field.name != new_field.name {
+            changes.push(
+                Kind::Source,
+                subject,
+                new,
+                position,
+                format!(
+                    "field {} was renamed from {} to {}",
+                    old_field.number, old_field.name, new_field.name
+                ),
+            );
+        }
+    }
+
+    for new_field in new.item.fields.iter() {
+        let previously_reserved = is_reserved(&old.item.reserved_ranges, new_field.number)
+            || old.item.reserved_names.contains(&new_field.name);
+        if previously_reserved {
+            changes.push(
+                Kind::Wire,
+                subject,
+                new,
+                Some(new_field.position),
+                format!(
+                    "field {} ({}) reuses a reserved number or name",
+                    new_field.number, new_field.name
+                ),
+            );
+        }
+    }
+}
+
+fn compare_enums(changes: &mut Changes, subject: &str, old: &Located<Enum>, new: &Located<Enum>) {
+    for old_value in old.item.values.iter() {
+        match new
+            .item
+            .values
+            .iter()
+            .find(|v| v.number == old_value.number)
+        {
+            None => {
+                let kind = if is_reserved(&new.item.reserved_ranges, old_value.number) {
+                    Kind::Source
+                } else {
+                    Kind::Wire
+                };
+                changes.push(
+                    kind,
+                    subject,
+                    new,
+                    Some(new.item.position),
+                    format!(
+                        "value {} ({}) was deleted{}",
+                        old_value.number,
+                        old_value.name,
+                        if kind == Kind::Wire {
+                            " without reserving its number"
+                        } else {
+                            ""
+                        }
+                    ),
+                );
+            }
+            Some(new_value) if new_value.name != old_value.name => changes.push(
+                Kind::Source,
+                subject,
+                new,
+                Some(new_value.position),
+                format!(
+                    "value {} was renamed from {} to {}",
+                    old_value.number, old_value.name, new_value.name
+                ),
+            ),
+            Some(_) => {}
+        }
+    }
+}
+
+fn compare_services(
+    changes: &mut Changes,
+    subject: &str,
+    old: &Located<proto::Service>,
+    new: &Located<proto::Service>,
+) {
+    for old_rpc in old.item.rpcs.iter() {
+        let new_rpc = match new.item.rpcs.iter().find(|r| r.name == old_rpc.name) {
+            Some(rpc) => rpc,
+            None => {
+                changes.push(
+                    Kind::Wire,
+                    subject,
+                    new,
+                    Some(new.item.position),
+                    format!("rpc {} was deleted", old_rpc.name),
+                );
+                continue;
+            }
+        };
+
+        let position = Some(new_rpc.position);
+
+        if !same_type(&old_rpc.input_type, &new_rpc.input_type)
+            || !same_type(&old_rpc.output_type, &new_rpc.output_type)
+        {
+            changes.push(
+                Kind::Wire,
+                subject,
+                new,
+                position,
+                format!(
+                    "rpc {} changed signature from ({}) returns ({}) to ({}) returns ({})",
+                    old_rpc.name,
+                    old_rpc.input_type,
+                    old_rpc.output_type,
+                    new_rpc.input_type,
+                    new_rpc.output_type
+                ),
+            );
+        }
+
+        if old_rpc.client_streaming != new_rpc.client_streaming
+            || old_rpc.server_streaming != new_rpc.server_streaming
+        {
+            changes.push(
+                Kind::Wire,
+                subject,
+                new,
+                position,
+                format!("rpc {} changed streaming mode", old_rpc.name),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(contents: &str) -> Schema {
+        Schema::parse(vec![(PathBuf::from("test.proto"), contents.to_owned())])
+    }
+
+    fn descriptions(old: &str, new: &str) -> Vec<(Kind, String)> {
+        compare(&schema(old), &schema(new))
+            .into_iter()
+            .map(|c| (c.kind, c.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_schemas_have_no_breaking_changes() {
+        let contents = "syntax = \"proto3\";\
+                        \npackage org.repo;\
+                        \nmessage Foo { string name = 1; }\
+                        \nservice Bar { rpc Get (Foo) returns (Foo); }";
+
+        assert!(descriptions(contents, contents).is_empty());
+    }
+
+    #[test]
+    fn test_adding_fields_is_not_breaking() {
+        let old = "package org.repo;\nmessage Foo { string name = 1; }";
+        let new = "package org.repo;\nmessage Foo { string name = 1; int32 age = 2; }";
+
+        assert!(descriptions(old, new).is_empty());
+    }
+
+    #[test]
+    fn test_deleted_field_is_wire_breaking_unless_reserved() {
+        let old = "package org.repo;\nmessage Foo { string name = 1; int32 age = 2; }";
+        let unreserved = "package org.repo;\nmessage Foo { string name = 1; }";
+        let reserved = "package org.repo;\nmessage Foo { string name = 1; reserved 2; }";
+
+        assert_eq!(
+            vec![(
+                Kind::Wire,
+                "wire breaking change in message org.repo.Foo: field 2 (age) was deleted without reserving its number".to_owned()
+            )],
+            descriptions(old, unreserved)
+        );
+        assert_eq!(Kind::Source, descriptions(old, reserved)[0].0);
+    }
+
+    #[test]
+    fn test_changed_field_type_and_name() {
+        let old = "package org.repo;\nmessage Foo { string name = 1; }";
+        let new = "package org.repo;\nmessage Foo { int64 id = 1; }";
+
+        let changes = descriptions(old, new);
+        assert_eq!(2, changes.len());
+        assert!(changes[0].1.contains("changed type from string to int64"));
+        assert!(changes[1].1.contains("renamed from name to id"));
+    }
+
+    #[test]
+    fn test_qualified_and_relative_types_are_equivalent() {
+        let old = "package org.repo;\nmessage Bar {}\nmessage Foo { Bar bar = 1; }";
+        let new = "package org.repo;\nmessage Bar {}\nmessage Foo { .org.repo.Bar bar = 1; }";
+
+        assert!(descriptions(old, new).is_empty());
+    }
+
+    #[test]
+    fn test_reusing_reserved_number_is_wire_breaking() {
+        let old = "package org.repo;\nmessage Foo { reserved 2; }";
+        let new = "package org.repo;\nmessage Foo { string other = 2; }";
+
+        assert_eq!(Kind::Wire, descriptions(old, new)[0].0);
+    }
+
+    #[test]
+    fn test_enum_and_service_changes() {
+        let old = "package org.repo;\
+                   \nenum Status { UNKNOWN = 0; OK = 1; }\
+                   \nmessage Req {}\
+                   \nservice Svc { rpc A (Req) returns (Req); rpc B (Req) returns (Req); }";
+        let new = "package org.repo;\
+                   \nenum Status { STATUS_UNKNOWN = 0; }\
+                   \nmessage Req {}\
+                   \nservice Svc { rpc A (Req) returns (stream Req); }";
+
+        let changes = descriptions(old, new);
+        assert!(changes.contains(&(
+            Kind::Source,
+            "source breaking change in enum org.repo.Status: value 0 was renamed from UNKNOWN to STATUS_UNKNOWN".to_owned()
+        )));
+        assert!(changes.contains(&(
+            Kind::Wire,
+            "wire breaking change in enum org.repo.Status: value 1 (OK) was deleted without reserving its number".to_owned()
+        )));
+        assert!(changes.contains(&(
+            Kind::Wire,
+            "wire breaking change in service org.repo.Svc: rpc A changed streaming mode".to_owned()
+        )));
+        assert!(changes.contains(&(
+            Kind::Wire,
+            "wire breaking change in service org.repo.Svc: rpc B was deleted".to_owned()
+        )));
+    }
+
+    #[test]
+    fn test_deleted_message_is_source_breaking() {
+        let old = "package org.repo;\nmessage Foo { message Inner {} }";
+        let new = "package org.repo;\nmessage Foo {}";
+
+        assert_eq!(
+            vec![(
+                Kind::Source,
+                "source breaking change in message org.repo.Foo.Inner: message was deleted"
+                    .to_owned()
+            )],
+            descriptions(old, new)
+        );
+    }
+}
@@ -14,67 +14,520 @@
  * limitations under the License.
 */
 
+use crate::breaking::{self, Kind, Schema};
+use crate::config::{self, LintConfig, PackageLayout, Severity, PROTOVEND_YAML};
+use crate::git;
+use crate::lock::{self, vendor::PROTOS_OUTPUT_DIRECTORY, PROTOVEND_LOCK};
+use crate::proto::{self, Enum, Field, Message, ParseError, Position, ProtoFile};
+use crate::summary::{LintResult, Summary};
 use crate::Result;
 use crate::{git_url::GitUrl, PROTOS_DIRECTORY};
 use failure::format_err;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::fs;
 use std::io::Result as IoResult;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use walkdir::WalkDir;
 
-#[derive(Debug)]
+mod fix;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
 enum ErrorCode {
     P001,
     P002,
+    P003,
+    P004,
+    P005,
+    P006,
+    P007,
+    P008,
+    P009,
+    P010,
+    P011,
+    P012,
+    P013,
+    P014,
+    P015,
+    P016,
+}
+
+impl ErrorCode {
+    fn is_style(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::P007 | ErrorCode::P008 | ErrorCode::P009 | ErrorCode::P010 | ErrorCode::P011
+        )
+    }
+
+    /// Style rules only warn unless configured otherwise, so linting existing producers keeps
+    /// working.
+    fn default_severity(&self) -> Severity {
+        if self.is_style() {
+            Severity::Warn
+        } else {
+            Severity::Error
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::P001 => "Proto files must not be stored in the root proto folder",
+            ErrorCode::P002 => "Proto folder must contain the project's repository path",
+            ErrorCode::P003 => "Proto files must be syntactically valid",
+            ErrorCode::P004 => "Proto packages must match the configured layout",
+            ErrorCode::P005 => "Protos must not contain wire breaking changes",
+            ErrorCode::P006 => "Protos must not contain source breaking changes",
+            ErrorCode::P007 => "Message, enum and service names must be PascalCase",
+            ErrorCode::P008 => "Field names must be snake_case",
+            ErrorCode::P009 => {
+                "Enum values must be UPPER_SNAKE_CASE and prefixed with the enum name"
+            }
+            ErrorCode::P010 => "The zero value of an enum must be named <PREFIX>_UNSPECIFIED",
+            ErrorCode::P011 => "Packages must end with a version, e.g. v1",
+            ErrorCode::P012 => "Vendored protos must not be missing",
+            ErrorCode::P013 => "Vendored protos must come from a locked dependency",
+            ErrorCode::P014 => "Vendored protos must not be edited",
+            ErrorCode::P015 => "The lock must be in sync with .protovend.yml",
+            ErrorCode::P016 => "Locked commits must be cached to check vendored protos",
+        }
+    }
 }
 
+impl FromStr for &'static ErrorCode {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ALL_ERROR_CODES
+            .iter()
+            .find(|code| format!("{:?}", code) == s)
+            .ok_or_else(|| format_err!("Unknown lint rule {}", s))
+    }
+}
+
+const ALL_ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode::P001,
+    ErrorCode::P002,
+    ErrorCode::P003,
+    ErrorCode::P004,
+    ErrorCode::P005,
+    ErrorCode::P006,
+    ErrorCode::P007,
+    ErrorCode::P008,
+    ErrorCode::P009,
+    ErrorCode::P010,
+    ErrorCode::P011,
+    ErrorCode::P012,
+    ErrorCode::P013,
+    ErrorCode::P014,
+    ErrorCode::P015,
+    ErrorCode::P016,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Sarif,
+    Github,
+}
+
+impl OutputFormat {
+    pub fn is_machine_readable(self) -> bool {
+        self != OutputFormat::Text
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "sarif" => Ok(OutputFormat::Sarif),
+            "github" => Ok(OutputFormat::Github),
+            _ => Err(format_err!(
+                "Unknown format {}; expected one of text, json, sarif or github",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LintOptions {
+    /// Git revision to compare the project's protos against for breaking changes.
+    pub breaking_against: Option<String>,
+    pub format: OutputFormat,
+    /// Move misplaced protos into proto/<sanitised_path> before checking.
+    pub fix: bool,
+    /// Only report what fixing would change.
+    pub dry_run: bool,
+    /// Also check vendored protos against the lock when the project has a .protovend.yml.
+    pub check_vendored: bool,
+    /// Report style rules, P007 to P011, even where the project's config does not set them.
+    pub check_style: bool,
+}
+
+type ParsedProto = (PathBuf, std::result::Result<ProtoFile, ParseError>);
+
 #[derive(Clone)]
 struct CheckResult {
     checked_resource: PathBuf,
+    position: Option<Position>,
     message: String,
     error_code: &'static ErrorCode,
 }
 
 impl Display for CheckResult {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(
-            f,
-            "{}: {:?} {}",
-            self.checked_resource.display(),
-            self.error_code,
-            self.message
-        )
+        write!(f, "{}", self.checked_resource.display())?;
+        if let Some(position) = self.position {
+            write!(f, ":{}", position)?;
+        }
+        write!(f, ": {:?} {}", self.error_code, self.message)
     }
 }
 
+/// Checks a dependency's protos before vendoring them. Style rules are skipped unless the
+/// producer's config sets them, as consumers cannot fix the producer's style.
 pub fn run_checks<P: AsRef<Path>>(project_root: P, url: &GitUrl) -> Result<()> {
+    let summary = Summary {
+        lint_results: run_lint(project_root, Some(url), &LintOptions::default())?,
+        ..Summary::default()
+    };
+    summary.error().map_or(Ok(()), Err)
+}
+
+/// Runs every check which applies to the project, reporting and returning the results. Without
+/// the project's URL, checks which depend on its expected proto/<sanitised_path> directory are
+/// skipped.
+pub fn run_lint<P: AsRef<Path>>(
+    project_root: P,
+    url: Option<&GitUrl>,
+    options: &LintOptions,
+) -> Result<Vec<LintResult>> {
     let proto_root_folder = project_root.as_ref().join(PROTOS_DIRECTORY.as_path());
-    let project_proto_dir = proto_root_folder.join(url.sanitised_path());
-    let relative_proto_dir = project_proto_dir.strip_prefix(&proto_root_folder)?;
+    let sanitised_path = url.map(GitUrl::sanitised_path);
+
+    if options.fix {
+        match &sanitised_path {
+            Some(sanitised_path) => fix::relocate_protos(
+                &proto_root_folder,
+                &project_root
+                    .as_ref()
+                    .join(PROTOS_OUTPUT_DIRECTORY.as_path()),
+                sanitised_path,
+                options.dry_run,
+            )?,
+            None => {
+                return Err(format_err!(
+                    "Cannot fix proto locations without the project's git url"
+                ))
+            }
+        }
+    }
 
     log::info!("Running protovend checks..");
 
-    let results: Vec<Result<Vec<CheckResult>>> = vec![
-        check_proto_directory_structure(project_proto_dir.as_path(), proto_root_folder.as_path()),
-        check_root_proto_folder_has_no_protos(relative_proto_dir, &proto_root_folder),
-    ];
-    let results: Result<Vec<Vec<CheckResult>>> = results.into_iter().collect();
-    let results = results?.concat();
+    let lint_config = config::load_lint_config(project_root.as_ref())?;
+    let protos = parse_protos(&proto_root_folder)?;
 
-    report(&results);
+    // Consumers without protos of their own have no producer layout to check
+    let is_consumer = options.check_vendored
+        && project_root
+            .as_ref()
+            .join(PROTOVEND_YAML.as_path())
+            .is_file();
+    let is_producer = proto_root_folder.exists() || !is_consumer;
 
-    if !results.is_empty() {
-        Err(format_err!("Validation errors reported"))
-    } else {
-        Ok(())
+    let mut results: Vec<Result<Vec<CheckResult>>> = Vec::new();
+    if is_producer {
+        match &sanitised_path {
+            Some(sanitised_path) => results.extend(vec![
+                check_proto_directory_structure(
+                    proto_root_folder.join(sanitised_path).as_path(),
+                    proto_root_folder.as_path(),
+                ),
+                check_root_proto_folder_has_no_protos(
+                    Path::new(sanitised_path),
+                    &proto_root_folder,
+                ),
+                check_package_matches_layout(
+                    &protos,
+                    &proto_root_folder,
+                    sanitised_path,
+                    lint_config.package_layout,
+                ),
+            ]),
+            None => {
+                log::warn!("Unable to find the project's git url, so only checks that do not depend on it will be run. Pass --url or set project_url in {} to run all checks", PROTOVEND_YAML.display());
+                results.push(check_root_proto_folder_has_no_protos(
+                    Path::new("<org>/<repo>"),
+                    &proto_root_folder,
+                ));
+            }
+        }
+        results.extend(vec![
+            check_protos_parse(&protos),
+            check_naming_conventions(&protos),
+            check_package_is_versioned(&protos),
+        ]);
+        if let Some(reference) = &options.breaking_against {
+            results.push(check_breaking_changes(
+                project_root.as_ref(),
+                &proto_root_folder,
+                reference,
+            ));
+        }
     }
+    if is_consumer {
+        results.push(check_vendored_protos(project_root.as_ref()));
+    }
+    let results: Result<Vec<Vec<CheckResult>>> = results.into_iter().collect();
+    let results: Vec<CheckResult> = results?
+        .concat()
+        .into_iter()
+        .filter(|result| {
+            options.check_style
+                || !result.error_code.is_style()
+                || lint_config
+                    .rules
+                    .contains_key(&format!("{:?}", result.error_code))
+        })
+        .collect();
+    let results = apply_severities(results, &lint_config);
+
+    report(&results, project_root.as_ref(), options.format);
+
+    Ok(results
+        .iter()
+        .map(|(result, severity)| result.to_lint_result(*severity, project_root.as_ref()))
+        .collect())
 }
 
-fn report(results: &[CheckResult]) {
+type ReportedResult = (CheckResult, Severity);
+
+/// Pairs each result with the severity configured for its rule, dropping results which are
+/// turned off or suppressed with a `// protovend:ignore P00x` comment in the proto file.
+fn apply_severities(results: Vec<CheckResult>, lint_config: &LintConfig) -> Vec<ReportedResult> {
+    let mut severities: HashMap<&'static ErrorCode, Severity> = HashMap::new();
+    for (rule, severity) in &lint_config.rules {
+        match rule.parse::<&'static ErrorCode>() {
+            Ok(code) => {
+                severities.insert(code, *severity);
+            }
+            Err(_) => log::warn!("Ignoring unknown lint rule {} in .protovend.yml", rule),
+        }
+    }
+
+    let mut suppressions: HashMap<PathBuf, Vec<(usize, &'static ErrorCode)>> = HashMap::new();
+    let mut reported = Vec::new();
+
     for result in results {
-        log::error!("{}", result);
+        let severity = severities
+            .get(result.error_code)
+            .cloned()
+            .unwrap_or_else(|| result.error_code.default_severity());
+        if severity == Severity::Off {
+            continue;
+        }
+
+        if let Some(position) = result.position {
+            if !suppressions.contains_key(&result.checked_resource) {
+                let contents = fs::read_to_string(&result.checked_resource).unwrap_or_default();
+                suppressions.insert(
+                    result.checked_resource.clone(),
+                    find_suppressions(&contents),
+                );
+            }
+            let suppressed = suppressions[&result.checked_resource]
+                .iter()
+                .any(|(line, code)| {
+                    *code == result.error_code
+                        && (*line == position.line || *line + 1 == position.line)
+                });
+            if suppressed {
+                log::debug!("Suppressed {}", result);
+                continue;
+            }
+        }
+
+        reported.push((result, severity));
+    }
+
+    reported
+}
+
+/// Rules ignored by `// protovend:ignore P00x` comments, which apply to the line they are on
+/// and the line following.
+fn find_suppressions(contents: &str) -> Vec<(usize, &'static ErrorCode)> {
+    const MARKER: &str = "protovend:ignore";
+
+    let mut suppressions = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let comment = match line.find("//") {
+            Some(start) => &line[start + 2..],
+            None => continue,
+        };
+        let rules = match comment.trim_start().strip_prefix(MARKER) {
+            Some(rules) => rules,
+            None => continue,
+        };
+        for rule in rules.split(|c: char| c.is_whitespace() || c == ',') {
+            if rule.is_empty() {
+                continue;
+            }
+            match rule.parse() {
+                Ok(code) => suppressions.push((index + 1, code)),
+                Err(_) => log::warn!("Ignoring unknown lint rule {} on line {}", rule, index + 1),
+            }
+        }
+    }
+    suppressions
+}
+
+fn report(results: &[ReportedResult], project_root: &Path, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => {
+            for (result, severity) in results {
+                if *severity == Severity::Warn {
+                    log::warn!("{}", result);
+                } else {
+                    log::error!("{}", result);
+                }
+            }
+        }
+        OutputFormat::Json => println!("{}", to_json(results, project_root)),
+        OutputFormat::Sarif => println!("{}", to_sarif(results, project_root)),
+        OutputFormat::Github => {
+            for result in results {
+                println!("{}", to_github_annotation(result, project_root));
+            }
+        }
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Warn => "warning",
+        _ => "error",
+    }
+}
+
+impl CheckResult {
+    /// Path of the checked resource relative to the project, as expected by CI tooling.
+    fn relative_path(&self, project_root: &Path) -> String {
+        self.checked_resource
+            .strip_prefix(project_root)
+            .unwrap_or(&self.checked_resource)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    fn to_lint_result(&self, severity: Severity, project_root: &Path) -> LintResult {
+        LintResult {
+            rule: format!("{:?}", self.error_code),
+            severity: severity_name(severity).to_owned(),
+            file: self.relative_path(project_root),
+            line: self.position.map(|p| p.line),
+            column: self.position.map(|p| p.column),
+            message: self.message.clone(),
+        }
+    }
+}
+
+fn to_json(results: &[ReportedResult], project_root: &Path) -> Value {
+    let results: Vec<LintResult> = results
+        .iter()
+        .map(|(result, severity)| result.to_lint_result(*severity, project_root))
+        .collect();
+    json!({ "results": results })
+}
+
+fn to_sarif(results: &[ReportedResult], project_root: &Path) -> Value {
+    let rules: Vec<Value> = ALL_ERROR_CODES
+        .iter()
+        .map(|code| {
+            json!({
+                "id": format!("{:?}", code),
+                "shortDescription": { "text": code.description() },
+            })
+        })
+        .collect();
+
+    let results: Vec<Value> = results
+        .iter()
+        .map(|(result, severity)| {
+            let mut location = json!({
+                "artifactLocation": { "uri": result.relative_path(project_root) },
+            });
+            if let Some(position) = result.position {
+                location["region"] = json!({
+                    "startLine": position.line,
+                    "startColumn": position.column,
+                });
+            }
+            json!({
+                "ruleId": format!("{:?}", result.error_code),
+                "level": severity_name(*severity),
+                "message": { "text": result.message },
+                "locations": [{ "physicalLocation": location }],
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "protovend",
+                    "version": crate::CRATE_VERSION.to_string(),
+                    "informationUri": "https://github.com/Skyscanner/protovend",
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+/// Formats a result as a GitHub Actions workflow command, annotating the file in PR diffs.
+fn to_github_annotation((result, severity): &ReportedResult, project_root: &Path) -> String {
+    let mut properties = format!(
+        "file={}",
+        escape_property(&result.relative_path(project_root))
+    );
+    if let Some(position) = result.position {
+        properties.push_str(&format!(",line={},col={}", position.line, position.column));
     }
+    properties.push_str(&format!(",title={:?}", result.error_code));
+    format!(
+        "::{} {}::{}",
+        severity_name(*severity),
+        properties,
+        escape_data(&result.message)
+    )
+}
+
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
 }
 
 fn check_root_proto_folder_has_no_protos<P: AsRef<Path>>(
@@ -96,6 +549,7 @@ fn check_root_proto_folder_has_no_protos<P: AsRef<Path>>(
                 if e.metadata()?.is_file() && e.path().extension() == Some(OsStr::new("proto")) {
                     Ok(Some(CheckResult {
                         checked_resource: proto_root_folder.as_ref().into(),
+                        position: None,
                         message: description.clone(),
                         error_code: &ErrorCode::P001,
                     }))
@@ -124,6 +578,7 @@ fn check_proto_directory_structure<P: AsRef<Path>>(
     let result = if !project_proto_dir.as_ref().exists() {
         vec![CheckResult {
             checked_resource: proto_root_folder.as_ref().into(),
+            position: None,
             message: description,
             error_code: &ErrorCode::P002,
         }]
@@ -132,3 +587,614 @@ fn check_proto_directory_structure<P: AsRef<Path>>(
     };
     Ok(result)
 }
+
+fn parse_protos<P: AsRef<Path>>(proto_root_folder: P) -> Result<Vec<ParsedProto>> {
+    let mut protos = Vec::new();
+
+    if !proto_root_folder.as_ref().exists() {
+        return Ok(protos);
+    }
+
+    for entry in WalkDir::new(proto_root_folder) {
+        let entry = entry?;
+        if entry.metadata()?.is_file() && entry.path().extension() == Some(OsStr::new("proto")) {
+            let parsed = proto::parse_file(entry.path())?;
+            protos.push((entry.into_path(), parsed));
+        }
+    }
+
+    Ok(protos)
+}
+
+fn check_protos_parse(protos: &[ParsedProto]) -> Result<Vec<CheckResult>> {
+    let results = protos
+        .iter()
+        .filter_map(|(path, parsed)| parsed.as_ref().err().map(|error| (path, error)))
+        .map(|(path, error)| CheckResult {
+            checked_resource: path.clone(),
+            position: Some(error.position),
+            message: format!(
+                "Proto file could not be parsed: {}. 
+    If source is from another repo please ask the owners to update",
+                error.message
+            ),
+            error_code: &ErrorCode::P003,
+        })
+        .collect();
+
+    Ok(results)
+}
+
+fn check_package_matches_layout<P: AsRef<Path>>(
+    protos: &[ParsedProto],
+    proto_root_folder: P,
+    sanitised_path: &str,
+    layout: PackageLayout,
+) -> Result<Vec<CheckResult>> {
+    let mut results = Vec::new();
+
+    if layout == PackageLayout::None {
+        return Ok(results);
+    }
+
+    let project_package = sanitised_path.replace('/', ".");
+
+    for (path, parsed) in protos {
+        let file = match parsed {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+
+        let relative_dir = path.strip_prefix(&proto_root_folder)?.parent().unwrap();
+        if !relative_dir.starts_with(sanitised_path) {
+            continue;
+        }
+
+        let expected = match layout {
+            PackageLayout::Directory => relative_dir
+                .iter()
+                .map(|c| c.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("."),
+            _ => project_package.clone(),
+        };
+
+        let (matches, description) = match (&file.package, layout) {
+            (None, _) => (false, "Proto file has no package declaration".to_owned()),
+            (Some(package), PackageLayout::Directory) => (
+                package.name == expected,
+                format!("Package {} does not match directory", package.name),
+            ),
+            (Some(package), _) => (
+                package.name == expected || package.name.starts_with(&format!("{}.", expected)),
+                format!("Package {} does not match project", package.name),
+            ),
+        };
+
+        if !matches {
+            results.push(CheckResult {
+                checked_resource: path.clone(),
+                position: file.package.as_ref().map(|p| p.position),
+                message: format!(
+                    "{}; packages should {} {} so generated code is predictable for consumers",
+                    description,
+                    if layout == PackageLayout::Directory {
+                        "be"
+                    } else {
+                        "start with"
+                    },
+                    expected
+                ),
+                error_code: &ErrorCode::P004,
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+fn check_vendored_protos(project_root: &Path) -> Result<Vec<CheckResult>> {
+    log::info!(
+        "Checking vendored protos against {}",
+        PROTOVEND_LOCK.display()
+    );
+
+    let config = config::load_config(&project_root.join(PROTOVEND_YAML.as_path()))?;
+    let lock_file = project_root.join(PROTOVEND_LOCK.as_path());
+    let lock = lock::load_lockfile(&lock_file)?;
+
+    let mut results: Vec<CheckResult> = lock
+        .differences_from(&config)
+        .into_iter()
+        .map(|difference| CheckResult {
+            checked_resource: lock_file.clone(),
+            position: None,
+            message: format!(
+                "{}; run protovend install to bring the lock in line with {}",
+                difference,
+                PROTOVEND_YAML.display()
+            ),
+            error_code: &ErrorCode::P015,
+        })
+        .collect();
+
+    let vendor_dir = project_root.join(PROTOS_OUTPUT_DIRECTORY.as_path());
+    let cached = lock.cached_files()?;
+    let mut expected = cached.files;
+    for (url, commit) in cached.uncached.iter() {
+        results.push(CheckResult {
+            checked_resource: lock_file.clone(),
+            position: None,
+            message: format!(
+                "Commit {} of {} is not in the protovend cache, so its vendored protos were not checked; run protovend install to fetch it",
+                commit, url
+            ),
+            error_code: &ErrorCode::P016,
+        });
+    }
+    let uncached_dirs: Vec<String> = cached
+        .uncached
+        .iter()
+        .map(|(url, _)| url.sanitised_path())
+        .collect();
+
+    if vendor_dir.exists() {
+        for entry in WalkDir::new(&vendor_dir) {
+            let entry = entry?;
+            // Only protos are vendored, so other files such as a .gitkeep are left alone
+            if !entry.metadata()?.is_file() || entry.path().extension() != Some(OsStr::new("proto"))
+            {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(&vendor_dir)?;
+            if uncached_dirs.iter().any(|dir| relative.starts_with(dir)) {
+                continue;
+            }
+            let (message, error_code) = match expected.remove(relative) {
+                None => (
+                    "File is not provided by any locked dependency; vendored protos are replaced on every install so it will be lost",
+                    &ErrorCode::P013,
+                ),
+                Some(contents) if String::from_utf8_lossy(&fs::read(entry.path())?) != contents => (
+                    "File differs from the locked commit of its dependency; vendored protos must not be edited, run protovend install to restore it",
+                    &ErrorCode::P014,
+                ),
+                Some(_) => continue,
+            };
+            results.push(CheckResult {
+                checked_resource: entry.into_path(),
+                position: None,
+                message: message.to_owned(),
+                error_code,
+            });
+        }
+    }
+
+    for relative in expected.keys() {
+        results.push(CheckResult {
+            checked_resource: vendor_dir.join(relative),
+            position: None,
+            message: String::from(
+                "File from a locked dependency is missing; run protovend install to restore it",
+            ),
+            error_code: &ErrorCode::P012,
+        });
+    }
+
+    Ok(results)
+}
+
+fn check_naming_conventions(protos: &[ParsedProto]) -> Result<Vec<CheckResult>> {
+    let mut results = Vec::new();
+
+    for (path, parsed) in protos {
+        let file = match parsed {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        let mut naming = NamingCheck {
+            path,
+            results: &mut results,
+        };
+
+        for message in &file.messages {
+            naming.message(message);
+        }
+        for enumeration in &file.enums {
+            naming.enumeration(enumeration);
+        }
+        for service in &file.services {
+            naming.type_name("Service", &service.name, service.position);
+        }
+        for extend in &file.extends {
+            naming.fields(&extend.fields);
+        }
+    }
+
+    Ok(results)
+}
+
+struct NamingCheck<'a> {
+    path: &'a Path,
+    results: &'a mut Vec<CheckResult>,
+}
+
+impl<'a> NamingCheck<'a> {
+    fn push(&mut self, position: Position, message: String, error_code: &'static ErrorCode) {
+        self.results.push(CheckResult {
+            checked_resource: self.path.into(),
+            position: Some(position),
+            message: format!(
+                "{}. 
+    If source is from another repo please ask the owners to update",
+                message
+            ),
+            error_code,
+        });
+    }
+
+    fn type_name(&mut self, kind: &str, name: &str, position: Position) {
+        if !is_pascal_case(name) {
+            self.push(
+                position,
+                format!(
+                    "{} {} should be PascalCase, e.g. {}, to match the generated code of most languages",
+                    kind,
+                    name,
+                    to_pascal_case(name)
+                ),
+                &ErrorCode::P007,
+            );
+        }
+    }
+
+    fn message(&mut self, message: &Message) {
+        self.type_name("Message", &message.name, message.position);
+        self.fields(&message.fields);
+        for nested in &message.messages {
+            self.message(nested);
+        }
+        for enumeration in &message.enums {
+            self.enumeration(enumeration);
+        }
+        for extend in &message.extends {
+            self.fields(&extend.fields);
+        }
+    }
+
+    fn fields(&mut self, fields: &[Field]) {
+        for field in fields {
+            if !is_snake_case(&field.name) {
+                self.push(
+                    field.position,
+                    format!(
+                        "Field {} should be snake_case, e.g. {}, so JSON and generated accessor names are predictable",
+                        field.name,
+                        to_upper_snake_case(&field.name).to_lowercase()
+                    ),
+                    &ErrorCode::P008,
+                );
+            }
+        }
+    }
+
+    fn enumeration(&mut self, enumeration: &Enum) {
+        self.type_name("Enum", &enumeration.name, enumeration.position);
+
+        let prefix = format!("{}_", to_upper_snake_case(&enumeration.name));
+        for value in &enumeration.values {
+            if !is_upper_snake_case(&value.name) || !value.name.starts_with(&prefix) {
+                self.push(
+                    value.position,
+                    format!(
+                        "Enum value {} should be UPPER_SNAKE_CASE and start with {}, as enum values share a namespace with their enclosing scope",
+                        value.name, prefix
+                    ),
+                    &ErrorCode::P009,
+                );
+            }
+        }
+
+        if let Some(zero) = enumeration.values.iter().find(|v| v.number == 0) {
+            if !zero.name.ends_with("_UNSPECIFIED") {
+                self.push(
+                    zero.position,
+                    format!(
+                        "Enum value {} should be named {}UNSPECIFIED, as the zero value is used when a value is not set",
+                        zero.name, prefix
+                    ),
+                    &ErrorCode::P010,
+                );
+            }
+        }
+    }
+}
+
+fn check_package_is_versioned(protos: &[ParsedProto]) -> Result<Vec<CheckResult>> {
+    lazy_static! {
+        static ref VERSION: Regex = Regex::new(r"^v\d+((alpha|beta)\d*)?$").unwrap();
+    }
+
+    let results = protos
+        .iter()
+        .filter_map(|(path, parsed)| {
+            let package = parsed.as_ref().ok()?.package.as_ref()?;
+            let last = package.name.rsplit('.').next().unwrap_or_default();
+            if VERSION.is_match(last) {
+                return None;
+            }
+            Some(CheckResult {
+                checked_resource: path.clone(),
+                position: Some(package.position),
+                message: format!(
+                    "Package {} should end with a version such as v1 or v1beta1, so breaking changes can be made in a new version alongside the old. 
+    If source is from another repo please ask the owners to update",
+                    package.name
+                ),
+                error_code: &ErrorCode::P011,
+            })
+        })
+        .collect();
+
+    Ok(results)
+}
+
+fn is_pascal_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_snake_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn is_upper_snake_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Splits an identifier into words on underscores and lower to upper case transitions.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c == '_' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            previous_lower = false;
+            continue;
+        }
+        if c.is_ascii_uppercase() && previous_lower {
+            words.push(std::mem::take(&mut current));
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn to_pascal_case(name: &str) -> String {
+    words(name)
+        .iter()
+        .map(|word| {
+            let lower = word.to_ascii_lowercase();
+            let mut chars = lower.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn to_upper_snake_case(name: &str) -> String {
+    words(name).join("_").to_ascii_uppercase()
+}
+
+fn check_breaking_changes<P: AsRef<Path>>(
+    project_root: &Path,
+    proto_root_folder: P,
+    reference: &str,
+) -> Result<Vec<CheckResult>> {
+    log::info!("Checking for breaking changes against {}", reference);
+
+    let old = git::read_local_protos_at(project_root, reference, PROTOS_DIRECTORY.as_path())?;
+
+    let mut new = Vec::new();
+    if proto_root_folder.as_ref().exists() {
+        for entry in WalkDir::new(&proto_root_folder) {
+            let entry = entry?;
+            if entry.metadata()?.is_file() && entry.path().extension() == Some(OsStr::new("proto"))
+            {
+                let relative = entry.path().strip_prefix(&proto_root_folder)?;
+                new.push((relative.into(), fs::read_to_string(entry.path())?));
+            }
+        }
+    }
+
+    let results = breaking::compare(&Schema::parse(old), &Schema::parse(new))
+        .into_iter()
+        .map(|change| CheckResult {
+            checked_resource: proto_root_folder.as_ref().join(&change.file),
+            position: change.position,
+            message: format!(
+                "{} is not backward compatible with {}: {}",
+                change.subject, reference, change.description
+            ),
+            error_code: match change.kind {
+                Kind::Wire => &ErrorCode::P005,
+                Kind::Source => &ErrorCode::P006,
+            },
+        })
+        .collect();
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(position: Option<Position>) -> CheckResult {
+        CheckResult {
+            checked_resource: PathBuf::from("/project/proto/org/repo/a.proto"),
+            position,
+            message: String::from("Something, wrong\n100%"),
+            error_code: &ErrorCode::P003,
+        }
+    }
+
+    #[test]
+    fn test_github_annotation_is_escaped() {
+        let annotation = to_github_annotation(
+            &(
+                result(Some(Position { line: 3, column: 7 })),
+                Severity::Error,
+            ),
+            Path::new("/project"),
+        );
+
+        assert_eq!(
+            "::error file=proto/org/repo/a.proto,line=3,col=7,title=P003::Something, wrong%0A100%25",
+            annotation
+        );
+    }
+
+    #[test]
+    fn test_sarif_region_is_omitted_without_position() {
+        let sarif = to_sarif(&[(result(None), Severity::Warn)], Path::new("/project"));
+        let location = &sarif["runs"][0]["results"][0]["locations"][0]["physicalLocation"];
+
+        assert_eq!(
+            "proto/org/repo/a.proto",
+            location["artifactLocation"]["uri"]
+        );
+        assert!(location.get("region").is_none());
+        assert_eq!(
+            ALL_ERROR_CODES.len(),
+            sarif["runs"][0]["tool"]["driver"]["rules"]
+                .as_array()
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_suppressions_are_found_in_comments() {
+        let contents = "syntax = \"proto3\";\
+                        \n// protovend:ignore P004\
+                        \npackage foo;\
+                        \nmessage Foo { // protovend:ignore P005, P006\
+                        \n  string url = 1; // not protovend:ignore P003\
+                        \n}";
+
+        assert_eq!(
+            vec![
+                (2, &ErrorCode::P004),
+                (4, &ErrorCode::P005),
+                (4, &ErrorCode::P006)
+            ],
+            find_suppressions(contents)
+        );
+    }
+
+    fn check_style(contents: &str) -> Vec<(usize, &'static ErrorCode)> {
+        let protos = vec![(PathBuf::from("a.proto"), proto::parse(contents))];
+        let mut results = check_naming_conventions(&protos).unwrap();
+        results.extend(check_package_is_versioned(&protos).unwrap());
+        results
+            .iter()
+            .map(|r| (r.position.unwrap().line, r.error_code))
+            .collect()
+    }
+
+    #[test]
+    fn test_conventional_proto_has_no_style_results() {
+        let contents = "syntax = \"proto3\";\
+                        \npackage org.repo.v1beta1;\
+                        \nmessage SearchRequest {\
+                        \n  string query_text = 1;\
+                        \n  map<string, int32> page_sizes = 2;\
+                        \n  enum PhoneType { PHONE_TYPE_UNSPECIFIED = 0; PHONE_TYPE_MOBILE = 1; }\
+                        \n}\
+                        \nservice SearchService { rpc Search(SearchRequest) returns (SearchRequest); }";
+
+        assert!(check_style(contents).is_empty());
+    }
+
+    #[test]
+    fn test_style_violations_are_reported() {
+        let contents = "syntax = \"proto3\";\
+                        \npackage org.repo;\
+                        \nmessage search_request {\
+                        \n  string queryText = 1;\
+                        \n}\
+                        \nenum PhoneType {\
+                        \n  UNKNOWN = 0;\
+                        \n  PHONE_TYPE_MOBILE = 1;\
+                        \n}";
+
+        assert_eq!(
+            vec![
+                (3, &ErrorCode::P007),
+                (4, &ErrorCode::P008),
+                (7, &ErrorCode::P009),
+                (7, &ErrorCode::P010),
+                (2, &ErrorCode::P011),
+            ],
+            check_style(contents)
+        );
+    }
+
+    #[test]
+    fn test_vendoring_skips_style_rules_the_producer_does_not_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = GitUrl::from_str("git@github.com:org/repo.git").unwrap();
+        let proto_dir = dir.path().join("proto/org/repo");
+        fs::create_dir_all(&proto_dir).unwrap();
+        fs::write(
+            proto_dir.join("a.proto"),
+            "syntax = \"proto3\";\npackage org.repo;\nmessage search_request {}",
+        )
+        .unwrap();
+        let rules = |options: &LintOptions| -> Vec<String> {
+            run_lint(dir.path(), Some(&url), options)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.rule)
+                .collect()
+        };
+
+        assert!(rules(&LintOptions::default()).is_empty());
+        let lint = LintOptions {
+            check_style: true,
+            ..LintOptions::default()
+        };
+        assert_eq!(vec!["P007", "P011"], rules(&lint));
+
+        fs::write(
+            dir.path().join(PROTOVEND_YAML.as_path()),
+            "min_protovend_version: 0.0.0\nvendor: []\nlint:\n  rules:\n    P011: error",
+        )
+        .unwrap();
+        assert_eq!(vec!["P011"], rules(&LintOptions::default()));
+        assert!(run_checks(dir.path(), &url).is_err());
+    }
+
+    #[test]
+    fn test_names_are_converted_between_cases() {
+        assert_eq!("SearchRequest", to_pascal_case("search_request"));
+        assert_eq!("PHONE_TYPE", to_upper_snake_case("PhoneType"));
+        assert_eq!("HTTP2_CONFIG", to_upper_snake_case("HTTP2Config"));
+    }
+}
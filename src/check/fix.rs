@@ -0,0 +1,368 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use crate::lock::vendor::swap_directory;
+use crate::proto::{self, Position};
+use crate::{util, Result};
+use failure::format_err;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Moves protos outside of proto/<sanitised_path> into it, keeping their layout relative to
+/// proto/, and rewrites imports of the moved files to match. Directories copied from the
+/// vendored protos under `vendor_root` and laid out by package, such as a copy of google/api,
+/// belong to another repo and are left in place. The moves are made in a copy of proto/ which
+/// replaces it once complete.
+pub(super) fn relocate_protos(
+    proto_root: &Path,
+    vendor_root: &Path,
+    sanitised_path: &str,
+    dry_run: bool,
+) -> Result<()> {
+    let verb = if dry_run { "Would move" } else { "Moving" };
+
+    let files = find_protos(proto_root)?;
+    let foreign = foreign_directories(proto_root, vendor_root, &files, sanitised_path)?;
+    for directory in &foreign {
+        log::info!(
+            "Leaving {} in place as its protos are vendored from another repo",
+            proto_root.join(directory).display()
+        );
+    }
+    let moves: BTreeMap<String, String> = files
+        .iter()
+        .filter(|file| !Path::new(file).starts_with(sanitised_path))
+        .filter(|file| !top_directory(file).is_some_and(|dir| foreign.contains(dir)))
+        .map(|file| (file.clone(), format!("{}/{}", sanitised_path, file)))
+        .collect();
+
+    if moves.is_empty() {
+        log::info!("No proto files need to be moved");
+        return Ok(());
+    }
+
+    for to in moves.values() {
+        if proto_root.join(to).exists() {
+            return Err(format_err!(
+                "Cannot move proto files as {} already exists",
+                proto_root.join(to).display()
+            ));
+        }
+    }
+
+    let mut rewrites = BTreeMap::new();
+    for file in &files {
+        let contents = fs::read_to_string(proto_root.join(file))?;
+        let (rewritten, count) = rewrite_imports(&contents, &moves);
+        if count > 0 {
+            rewrites.insert(file.clone(), (rewritten, count));
+        }
+    }
+
+    for (from, to) in &moves {
+        log::info!(
+            "{} {} -> {}",
+            verb,
+            proto_root.join(from).display(),
+            proto_root.join(to).display()
+        );
+    }
+    for (file, (_, count)) in &rewrites {
+        log::info!(
+            "{} {} import(s) in {}",
+            if dry_run {
+                "Would rewrite"
+            } else {
+                "Rewriting"
+            },
+            count,
+            proto_root.join(moves.get(file).unwrap_or(file)).display()
+        );
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    let staged = util::staging_path(proto_root, "tmp");
+    if staged.exists() {
+        fs::remove_dir_all(&staged)?;
+    }
+    let result = copy_relocated(proto_root, &staged, &moves, &rewrites)
+        .and_then(|_| swap_directory(&staged, proto_root, || Ok(())));
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&staged);
+        return Err(e);
+    }
+
+    log::info!(
+        "Moved {} proto file(s) and rewrote imports in {} file(s)",
+        moves.len(),
+        rewrites.len()
+    );
+
+    Ok(())
+}
+
+/// Proto files under the proto root, as import paths relative to it.
+fn find_protos(proto_root: &Path) -> Result<Vec<String>> {
+    let mut protos = Vec::new();
+
+    if !proto_root.exists() {
+        return Ok(protos);
+    }
+
+    for entry in WalkDir::new(proto_root).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry?;
+        if entry.metadata()?.is_file() && entry.path().extension() == Some(OsStr::new("proto")) {
+            protos.push(to_import_path(entry.path().strip_prefix(proto_root)?));
+        }
+    }
+
+    Ok(protos)
+}
+
+fn to_import_path(path: &Path) -> String {
+    path.iter()
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn top_directory(file: &str) -> Option<&str> {
+    file.find('/').map(|end| &file[..end])
+}
+
+/// Top level directories outside the project's own holding protos which are also vendored and
+/// whose package matches their directory, e.g. `google/api/http.proto` declaring
+/// `package google.api;`.
+fn foreign_directories<'a>(
+    proto_root: &Path,
+    vendor_root: &Path,
+    files: &'a [String],
+    sanitised_path: &str,
+) -> Result<BTreeSet<&'a str>> {
+    let mut foreign = BTreeSet::new();
+    for file in files {
+        let (top, directory) = match (top_directory(file), file.rfind('/')) {
+            (Some(top), Some(end)) => (top, &file[..end]),
+            _ => continue,
+        };
+        if Path::new(file).starts_with(sanitised_path)
+            || foreign.contains(top)
+            || !vendor_root.join(file).is_file()
+        {
+            continue;
+        }
+
+        let contents = fs::read_to_string(proto_root.join(file))?;
+        let package = proto::parse(&contents)
+            .ok()
+            .and_then(|parsed| parsed.package)
+            .map(|package| package.name);
+        if package == Some(directory.replace('/', ".")) {
+            foreign.insert(top);
+        }
+    }
+    Ok(foreign)
+}
+
+/// Copies everything under the proto root to `staged`, with the moves and rewrites applied.
+/// Symlinks are copied as links and empty directories are kept.
+fn copy_relocated(
+    proto_root: &Path,
+    staged: &Path,
+    moves: &BTreeMap<String, String>,
+    rewrites: &BTreeMap<String, (String, usize)>,
+) -> Result<()> {
+    for entry in WalkDir::new(proto_root) {
+        let entry = entry?;
+        let file = to_import_path(entry.path().strip_prefix(proto_root)?);
+        let destination = staged.join(moves.get(&file).unwrap_or(&file));
+
+        if entry.file_type().is_dir() {
+            if fs::read_dir(entry.path())?.next().is_none() {
+                fs::create_dir_all(&destination)?;
+            }
+            continue;
+        }
+        fs::create_dir_all(destination.parent().unwrap())?;
+        if entry.file_type().is_symlink() {
+            copy_symlink(entry.path(), &destination)?;
+            continue;
+        }
+        match rewrites.get(&file) {
+            Some((contents, _)) => fs::write(&destination, contents)?,
+            None => {
+                fs::copy(entry.path(), &destination)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(link: &Path, destination: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(link)?, destination)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn copy_symlink(link: &Path, destination: &Path) -> Result<()> {
+    let target = fs::read_link(link)?;
+    if fs::metadata(link).map(|m| m.is_dir()).unwrap_or(false) {
+        std::os::windows::fs::symlink_dir(target, destination)?;
+    } else {
+        std::os::windows::fs::symlink_file(target, destination)?;
+    }
+    Ok(())
+}
+
+/// Replaces imports of renamed files, returning the new contents and how many were replaced.
+/// Imports are found with the proto tokenizer, so text in comments is left alone.
+fn rewrite_imports(contents: &str, renames: &BTreeMap<String, String>) -> (String, usize) {
+    let mut rewritten = String::new();
+    let mut copied = 0;
+    let mut count = 0;
+
+    for (position, path) in proto::spanned_imports(contents) {
+        let renamed = match renames.get(&path) {
+            Some(renamed) => renamed,
+            None => continue,
+        };
+        let start = byte_offset(contents, position);
+        let quote = contents[start..].chars().next().unwrap();
+        rewritten.push_str(&contents[copied..start]);
+        rewritten.push(quote);
+        rewritten.push_str(renamed);
+        rewritten.push(quote);
+        copied = string_literal_end(contents, start);
+        count += 1;
+    }
+    rewritten.push_str(&contents[copied..]);
+
+    (rewritten, count)
+}
+
+/// Offset of a position reported by the tokenizer, whose columns count characters.
+fn byte_offset(contents: &str, position: Position) -> usize {
+    let line_start: usize = contents
+        .split_inclusive('\n')
+        .take(position.line - 1)
+        .map(str::len)
+        .sum();
+    line_start
+        + contents[line_start..]
+            .chars()
+            .take(position.column - 1)
+            .map(char::len_utf8)
+            .sum::<usize>()
+}
+
+/// Offset just past the string literal starting at `start`.
+fn string_literal_end(contents: &str, start: usize) -> usize {
+    let mut chars = contents[start..].char_indices();
+    let (_, quote) = chars.next().unwrap();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == quote {
+            return start + i + c.len_utf8();
+        }
+    }
+    contents.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imports_of_moved_files_are_rewritten() {
+        let mut renames = BTreeMap::new();
+        renames.insert(
+            String::from("common/types.proto"),
+            String::from("org/repo/common/types.proto"),
+        );
+        let contents = "syntax = \"proto3\";\
+                        \nimport \"common/types.proto\";\
+                        \n  import public 'common/types.proto';\
+                        \n/* é */ import \"google/protobuf/timestamp.proto\"; import \"common/types.proto\";\
+                        \n// import \"common/types.proto\";\
+                        \n/*\
+                        \nimport \"common/types.proto\"; */";
+
+        let (rewritten, count) = rewrite_imports(contents, &renames);
+
+        assert_eq!(3, count);
+        assert_eq!(
+            "syntax = \"proto3\";\
+             \nimport \"org/repo/common/types.proto\";\
+             \n  import public 'org/repo/common/types.proto';\
+             \n/* é */ import \"google/protobuf/timestamp.proto\"; import \"org/repo/common/types.proto\";\
+             \n// import \"common/types.proto\";\
+             \n/*\
+             \nimport \"common/types.proto\"; */",
+            rewritten
+        );
+    }
+
+    #[test]
+    fn test_vendored_directories_laid_out_by_package_are_left_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let vendor = tempfile::tempdir().unwrap();
+        let write = |root: &Path, path: &str, contents: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        let write_proto = |path: &str, contents: &str| write(dir.path(), path, contents);
+        for root in &[dir.path(), vendor.path()] {
+            write(
+                root,
+                "google/api/http.proto",
+                "syntax = \"proto3\";\npackage google.api;",
+            );
+        }
+        write_proto("foo/v1/foo.proto", "syntax = \"proto3\";\npackage foo.v1;");
+        write_proto(
+            "common/types.proto",
+            "syntax = \"proto3\";\npackage org.repo.common;",
+        );
+        write_proto(
+            "service.proto",
+            "syntax = \"proto3\";\nimport \"common/types.proto\";",
+        );
+        write_proto("README.md", "Protos");
+
+        relocate_protos(dir.path(), vendor.path(), "org/repo", false).unwrap();
+
+        assert!(dir.path().join("google/api/http.proto").is_file());
+        assert!(dir.path().join("org/repo/foo/v1/foo.proto").is_file());
+        assert!(!dir.path().join("foo").exists());
+        assert!(dir.path().join("org/repo/common/types.proto").is_file());
+        assert!(dir.path().join("org/repo/service.proto").is_file());
+        assert!(dir.path().join("README.md").is_file());
+        assert!(!dir.path().join("common").exists());
+        assert_eq!(
+            "syntax = \"proto3\";\nimport \"org/repo/common/types.proto\";",
+            fs::read_to_string(dir.path().join("org/repo/service.proto")).unwrap()
+        );
+    }
+}
@@ -23,8 +23,9 @@ use log;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use serde_yaml;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 #[cfg(test)]
@@ -39,6 +40,8 @@ lazy_static! {
 pub struct Dependency {
     pub url: GitUrl,
     pub branch: String,
+    #[serde(default, skip_serializing_if = "util::is_false")]
+    pub transitive: bool,
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
@@ -48,10 +51,55 @@ pub struct GithubDependency {
     pub host: Host,
 }
 
+/// How a proto file's `package` must relate to its location under proto/<sanitised_path>.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageLayout {
+    /// Packages are not checked.
+    #[default]
+    None,
+    /// The package must equal the file's directory relative to proto/, e.g. `org.repo.v1`.
+    Directory,
+    /// The package must start with the project's sanitised path, e.g. `org.repo`.
+    Prefix,
+}
+
+/// How a failing lint rule is reported.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Reported and fails the lint.
+    Error,
+    /// Reported without failing the lint.
+    Warn,
+    /// Not reported.
+    Off,
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct LintConfig {
+    #[serde(default)]
+    pub package_layout: PackageLayout,
+    /// Severity overrides keyed by rule ID, e.g. `P004`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rules: BTreeMap<String, Severity>,
+}
+
+impl LintConfig {
+    fn is_default(&self) -> bool {
+        *self == LintConfig::default()
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProtovendConfig {
     pub min_protovend_version: Version,
     pub vendor: Vec<Dependency>,
+    /// The project's own repo, for linting checkouts without a usable git remote.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_url: Option<GitUrl>,
+    #[serde(default, skip_serializing_if = "LintConfig::is_default")]
+    pub lint: LintConfig,
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
@@ -64,6 +112,10 @@ pub struct LegacyProtovendConfig {
 pub struct EmptyProtovendConfig {
     pub min_protovend_version: Version,
     pub vendor: (),
+    #[serde(default)]
+    pub project_url: Option<GitUrl>,
+    #[serde(default)]
+    pub lint: LintConfig,
 }
 
 impl From<EmptyProtovendConfig> for ProtovendConfig {
@@ -71,6 +123,8 @@ impl From<EmptyProtovendConfig> for ProtovendConfig {
         ProtovendConfig {
             min_protovend_version: empty.min_protovend_version,
             vendor: vec![],
+            project_url: empty.project_url,
+            lint: empty.lint,
         }
     }
 }
@@ -80,6 +134,8 @@ impl From<LegacyProtovendConfig> for ProtovendConfig {
         ProtovendConfig {
             min_protovend_version: legacy_config.min_protovend_version,
             vendor: legacy_config.vendor.into_iter().map(|d| d.into()).collect(),
+            project_url: None,
+            lint: LintConfig::default(),
         }
     }
 }
@@ -91,6 +147,7 @@ impl From<GithubDependency> for Dependency {
         Dependency {
             url,
             branch: dep.branch,
+            transitive: false,
         }
     }
 }
@@ -105,57 +162,69 @@ enum Config {
 
 impl ProtovendConfig {
     pub fn write(&mut self) -> Result<()> {
-        let f = File::create(PROTOVEND_YAML.as_path())?;
         self.vendor.sort_by(|a, b| a.url.cmp(&b.url));
-        serde_yaml::to_writer(f, &self).map_err(|e| e.into())
+        util::write_atomic(PROTOVEND_YAML.as_path(), serde_yaml::to_string(&self)?)
     }
 
-    pub fn add_dependency(&mut self, url: GitUrl, branch: String) -> Result<()> {
+    /// Adds or updates a dependency, returning whether the config was changed.
+    pub fn add_dependency(
+        &mut self,
+        url: GitUrl,
+        branch: String,
+        transitive: bool,
+    ) -> Result<bool> {
         let existing_dep = self.vendor.iter_mut().find(|dep| dep.url == url);
 
         match existing_dep {
             Some(dep) => {
-                if dep.branch == branch {
+                if dep.branch == branch && dep.transitive == transitive {
                     log::info!(
                         "{} has already added to {}",
                         url,
                         PROTOVEND_YAML.to_string_lossy()
                     );
-                    Ok(())
+                    Ok(false)
                 } else {
                     dep.branch = branch.clone();
-                    self.write()
-                        .map(|_| log::info!("Updated {} to use branch {}", url, branch))
+                    dep.transitive = transitive;
+                    self.write()?;
+                    log::info!("Updated {} to use branch {}", url, branch);
+                    Ok(true)
                 }
             }
             None => {
                 let new = Dependency {
                     url: url.clone(),
                     branch,
+                    transitive,
                 };
                 self.vendor.push(new);
-                self.write()
-                    .map(|_| log::info!("{} added to protovend metadata", url))
+                self.write()?;
+                log::info!("{} added to protovend metadata", url);
+                Ok(true)
             }
         }
     }
 }
 
-pub fn init() -> Result<()> {
+/// Creates the config if it does not exist, returning whether it was created.
+pub fn init() -> Result<bool> {
     if PROTOVEND_YAML.exists() {
         log::warn!(
             "{} file already exists in project",
             PROTOVEND_YAML.to_string_lossy()
         );
-        Ok(())
+        Ok(false)
     } else {
         let mut config = ProtovendConfig {
             min_protovend_version: crate::CRATE_VERSION.clone(),
             vendor: Vec::new(),
+            project_url: None,
+            lint: LintConfig::default(),
         };
-        config
-            .write()
-            .map(|_| log::info!("Created {}", PROTOVEND_YAML.as_path().to_string_lossy()))
+        config.write()?;
+        log::info!("Created {}", PROTOVEND_YAML.as_path().to_string_lossy());
+        Ok(true)
     }
 }
 
@@ -163,9 +232,30 @@ pub fn get_config() -> Result<ProtovendConfig> {
     load_config(&PROTOVEND_YAML)
 }
 
-fn load_config(config_file: &PathBuf) -> Result<ProtovendConfig> {
+/// The `project_url` declared in the .protovend.yml at the given root, if any.
+pub fn load_project_url(project_root: &Path) -> Result<Option<GitUrl>> {
+    let config_file = project_root.join(PROTOVEND_YAML.as_path());
+    if config_file.is_file() {
+        Ok(load_config(&config_file)?.project_url)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Lint settings for the project at the given root. Producers are not required to have a
+/// .protovend.yml, in which case the defaults apply.
+pub fn load_lint_config(project_root: &Path) -> Result<LintConfig> {
+    let config_file = project_root.join(PROTOVEND_YAML.as_path());
+    if config_file.is_file() {
+        Ok(load_config(&config_file)?.lint)
+    } else {
+        Ok(LintConfig::default())
+    }
+}
+
+pub(crate) fn load_config(config_file: &Path) -> Result<ProtovendConfig> {
     if config_file.is_file() {
-        let f = File::open(config_file.as_path())?;
+        let f = File::open(config_file)?;
         let config: Config = serde_yaml::from_reader(f)?;
 
         let config: ProtovendConfig = match config {
@@ -210,7 +300,38 @@ mod tests {
                 )
                 .unwrap(),
                 branch: String::from("master"),
+                transitive: false,
+            }],
+            project_url: None,
+            lint: LintConfig::default(),
+        };
+
+        let actual_config = load_config(&config_path).unwrap();
+
+        assert_eq!(expected_config, actual_config);
+    }
+
+    #[test]
+    fn test_correctly_parses_transitive_config() {
+        let config_contents = "--- \
+                               \nmin_protovend_version: 0.1.8 \
+                               \nvendor: \
+                               \n  - url: git@github.com:somegroup/producer-service.git \
+                               \n    branch: master \
+                               \n    transitive: true";
+
+        let config_path =
+            tests_utils::fs::write_contents_to_temp_file(config_contents, "transitive_config");
+
+        let expected_config = ProtovendConfig {
+            min_protovend_version: Version::from_str("0.1.8").unwrap(),
+            vendor: vec![Dependency {
+                url: GitUrl::from_str("git@github.com:somegroup/producer-service.git").unwrap(),
+                branch: String::from("master"),
+                transitive: true,
             }],
+            project_url: None,
+            lint: LintConfig::default(),
         };
 
         let actual_config = load_config(&config_path).unwrap();
@@ -238,7 +359,10 @@ mod tests {
                 )
                 .unwrap(),
                 branch: String::from("master"),
+                transitive: false,
             }],
+            project_url: None,
+            lint: LintConfig::default(),
         };
 
         let actual_config = load_config(&config_path).unwrap();
@@ -258,6 +382,8 @@ mod tests {
         let expected_config = ProtovendConfig {
             min_protovend_version: Version::from_str("0.1.8").unwrap(),
             vendor: vec![],
+            project_url: None,
+            lint: LintConfig::default(),
         };
 
         let actual_config = load_config(&config_path).unwrap();
@@ -265,6 +391,60 @@ mod tests {
         assert_eq!(expected_config, actual_config);
     }
 
+    #[test]
+    fn test_correctly_parses_lint_config() {
+        let config_contents = "--- \
+                               \nmin_protovend_version: 0.1.8 \
+                               \nvendor: \
+                               \nlint: \
+                               \n  package_layout: directory";
+
+        let config_path =
+            tests_utils::fs::write_contents_to_temp_file(config_contents, "lint_config");
+
+        let actual_config = load_config(&config_path).unwrap();
+
+        assert_eq!(PackageLayout::Directory, actual_config.lint.package_layout);
+    }
+
+    #[test]
+    fn test_correctly_parses_project_url() {
+        let config_contents = "--- \
+                               \nmin_protovend_version: 0.1.8 \
+                               \nproject_url: git@github.com:org/repo.git \
+                               \nvendor:";
+
+        let config_path =
+            tests_utils::fs::write_contents_to_temp_file(config_contents, "project_url_config");
+
+        let actual_config = load_config(&config_path).unwrap();
+
+        assert_eq!(
+            Some(GitUrl::from_str("git@github.com:org/repo.git").unwrap()),
+            actual_config.project_url
+        );
+    }
+
+    #[test]
+    fn test_correctly_parses_lint_rule_severities() {
+        let config_contents = "--- \
+                               \nmin_protovend_version: 0.1.8 \
+                               \nvendor: \
+                               \nlint: \
+                               \n  rules: \
+                               \n    P001: off \
+                               \n    P006: warn";
+
+        let config_path =
+            tests_utils::fs::write_contents_to_temp_file(config_contents, "lint_rules_config");
+
+        let actual_config = load_config(&config_path).unwrap();
+
+        assert_eq!(Some(&Severity::Off), actual_config.lint.rules.get("P001"));
+        assert_eq!(Some(&Severity::Warn), actual_config.lint.rules.get("P006"));
+        assert_eq!(PackageLayout::None, actual_config.lint.package_layout);
+    }
+
     #[test]
     fn test_config_from_legacy_config() {
         let legacy_config = LegacyProtovendConfig {
@@ -286,7 +466,10 @@ mod tests {
                 )
                 .unwrap(),
                 branch: String::from("master"),
+                transitive: false,
             }],
+            project_url: None,
+            lint: LintConfig::default(),
         };
 
         let actual_config = ProtovendConfig::from(legacy_config);
@@ -299,11 +482,15 @@ mod tests {
         let legacy_config = EmptyProtovendConfig {
             min_protovend_version: Version::from_str("0.1.8").unwrap(),
             vendor: (),
+            project_url: None,
+            lint: LintConfig::default(),
         };
 
         let expected_config = ProtovendConfig {
             min_protovend_version: Version::from_str("0.1.8").unwrap(),
             vendor: vec![],
+            project_url: None,
+            lint: LintConfig::default(),
         };
 
         let actual_config = ProtovendConfig::from(legacy_config);
@@ -16,13 +16,66 @@
 
 use crate::git_url::GitUrl;
 use crate::{util, Result};
-use git2::{build::CheckoutBuilder, Oid, Repository, ResetType};
-use std::fs;
+use chrono::{DateTime, TimeZone, Utc};
+use failure::format_err;
+use fs2::FileExt;
+use git2::{
+    build::CheckoutBuilder, ObjectType, Oid, Repository, ResetType, TreeWalkMode, TreeWalkResult,
+};
+use semver::Version;
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
 
+pub mod cache;
 mod commands;
 
+/// How long to wait for another process using a cached clone, unless overridden in seconds by
+/// the PROTOVEND_LOCK_TIMEOUT environment variable.
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A cached clone, locked against use by other protovend processes until dropped.
+pub struct LockedRepo {
+    repo: Repository,
+    _lock: File,
+}
+
+impl Deref for LockedRepo {
+    type Target = Repository;
+
+    fn deref(&self) -> &Repository {
+        &self.repo
+    }
+}
+
+/// A commit as reported to users.
+pub struct CommitInfo {
+    pub id: Oid,
+    pub summary: String,
+    pub author: String,
+    pub time: DateTime<Utc>,
+}
+
+impl CommitInfo {
+    fn from_commit(commit: &git2::Commit) -> Self {
+        CommitInfo {
+            id: commit.id(),
+            summary: commit.summary().unwrap_or_default().to_owned(),
+            author: commit.author().name().unwrap_or_default().to_owned(),
+            time: Utc
+                .timestamp_opt(commit.time().seconds(), 0)
+                .single()
+                .unwrap_or_else(Utc::now),
+        }
+    }
+}
+
 pub fn get_latest_commit_sha(url: &GitUrl, branch_name: &str) -> Result<Oid> {
     log::info!(
         "Fetching latest commit hash from {} branch of {}",
@@ -34,17 +87,19 @@ pub fn get_latest_commit_sha(url: &GitUrl, branch_name: &str) -> Result<Oid> {
     Ok(commit.id())
 }
 
-pub fn get_repo(url: &GitUrl, branch: &str, revision: &str) -> Result<Repository> {
-    let destination_path = get_destination_path(&url);
+pub fn get_repo(url: &GitUrl, branch: &str, revision: &str) -> Result<LockedRepo> {
+    let destination_path = get_destination_path(&crate::REPOS_CACHE_DIRECTORY, url);
+    let lock = lock_clone(url, &destination_path, lock_timeout())?;
+    migrate_legacy_clone(&crate::REPOS_CACHE_DIRECTORY, url, &destination_path)?;
 
-    if destination_path.exists() {
+    let repo = if is_usable_clone(url, &destination_path)? {
         log::debug!(
             "Checking out {} under branch {} for revision {}",
             url,
             branch,
             revision
         );
-        reset_local_repo_to_commit(&destination_path, branch, revision)
+        reset_local_repo_to_commit(&destination_path, branch, revision)?
     } else {
         log::debug!("Cloning {} to {}", url, destination_path.display());
         let repo = clone(url.as_str(), &destination_path, branch)?; //FIXME push GitUrl further down
@@ -52,18 +107,263 @@ pub fn get_repo(url: &GitUrl, branch: &str, revision: &str) -> Result<Repository
             let commit = repo.revparse_single(revision)?;
             repo.reset(&commit, ResetType::Hard, None)?;
         }
-        Ok(repo)
+        repo
+    };
+    Ok(LockedRepo { repo, _lock: lock })
+}
+
+/// The cached clone of `url`, with every branch and tag fetched but the working tree left as
+/// it is.
+pub fn fetch_repo(url: &GitUrl, branch: &str) -> Result<LockedRepo> {
+    let destination_path = get_destination_path(&crate::REPOS_CACHE_DIRECTORY, url);
+    let lock = lock_clone(url, &destination_path, lock_timeout())?;
+    migrate_legacy_clone(&crate::REPOS_CACHE_DIRECTORY, url, &destination_path)?;
+
+    let repo = if is_usable_clone(url, &destination_path)? {
+        log::debug!("Fetching {} to {}", url, destination_path.display());
+        commands::fetch_all(&destination_path, "origin")?;
+        Repository::open(&destination_path)?
+    } else {
+        log::debug!("Cloning {} to {}", url, destination_path.display());
+        clone(url.as_str(), &destination_path, branch)?
+    };
+    Ok(LockedRepo { repo, _lock: lock })
+}
+
+/// The cached clone of `url` as it is, without fetching, or `None` if it has not been cloned.
+pub fn cached_repo(url: &GitUrl) -> Result<Option<LockedRepo>> {
+    let destination_path = get_destination_path(&crate::REPOS_CACHE_DIRECTORY, url);
+    let lock = lock_clone(url, &destination_path, lock_timeout())?;
+    migrate_legacy_clone(&crate::REPOS_CACHE_DIRECTORY, url, &destination_path)?;
+
+    if verify_clone(&destination_path).is_err() {
+        return Ok(None);
+    }
+    Ok(Some(LockedRepo {
+        repo: Repository::open(&destination_path)?,
+        _lock: lock,
+    }))
+}
+
+/// Whether there is a cached clone at `destination_path` to reuse. Clones which cannot be
+/// used, for example because a previous run was killed part way through cloning, are removed
+/// so that they are cloned again.
+fn is_usable_clone(url: &GitUrl, destination_path: &Path) -> Result<bool> {
+    if !destination_path.exists() {
+        return Ok(false);
+    }
+
+    match verify_clone(destination_path) {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            log::warn!(
+                "Cached clone of {} is corrupted ({}); cloning it again",
+                url,
+                e
+            );
+            fs::remove_dir_all(destination_path)?;
+            Ok(false)
+        }
+    }
+}
+
+fn verify_clone(path: &Path) -> Result<()> {
+    let repo = Repository::open(path)?;
+    repo.find_remote("origin")?;
+    repo.head()?.peel_to_commit()?;
+    repo.index()?;
+
+    // Nothing else can be using the clone while we hold its lock
+    if path.join(".git/index.lock").exists() {
+        return Err(format_err!("left locked by an interrupted git command"));
+    }
+    Ok(())
+}
+
+/// Takes an exclusive lock on the cached clone at `destination_path`, waiting for other
+/// processes using it. The lock is released when the returned file is closed, including when
+/// the process holding it dies. The lock file records when the clone was last used.
+fn lock_clone<D: Display>(name: D, destination_path: &Path, timeout: Duration) -> Result<File> {
+    let lock_path = util::staging_path(destination_path, "lock");
+    if let Some(parent) = lock_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&lock_path)?;
+
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        match file.try_lock_exclusive() {
+            Ok(()) => {
+                file.set_len(0)?;
+                file.write_all(Utc::now().to_rfc3339().as_bytes())?;
+                return Ok(file);
+            }
+            Err(e) if e.kind() != fs2::lock_contended_error().kind() => return Err(e.into()),
+            Err(_) if start.elapsed() >= timeout => {
+                return Err(format_err!(
+                    "Timed out after {}s waiting for another protovend process to finish with {}",
+                    timeout.as_secs(),
+                    name
+                ))
+            }
+            Err(_) => {
+                if !waiting {
+                    log::info!(
+                        "Waiting for another protovend process to finish with {} (lock {})",
+                        name,
+                        lock_path.display()
+                    );
+                    waiting = true;
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+        }
+    }
+}
+
+fn lock_timeout() -> Duration {
+    env::var("PROTOVEND_LOCK_TIMEOUT")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_LOCK_TIMEOUT)
+}
+
+/// The newest revision available for `branch` in a fetched repo, with its name. This is the
+/// tip of the remote branch or, where `branch` is a version tag, the newest tag with the same
+/// major version.
+pub fn latest_revision(repo: &Repository, branch: &str) -> Result<(String, Oid)> {
+    if let Ok(reference) = repo.find_reference(&format!("refs/remotes/origin/{}", branch)) {
+        return Ok((branch.to_owned(), reference.peel_to_commit()?.id()));
+    }
+
+    let tags = repo.tag_names(None)?;
+    let tags: Vec<&str> = tags.iter().flatten().collect();
+    if !tags.contains(&branch) {
+        return Err(format_err!("No branch or tag named {} found", branch));
     }
+
+    let newest = newest_matching_tag(branch, &tags).unwrap_or(branch);
+    let commit = repo
+        .find_reference(&format!("refs/tags/{}", newest))?
+        .peel_to_commit()?;
+    Ok((newest.to_owned(), commit.id()))
+}
+
+fn parse_tag_version(tag: &str) -> Option<Version> {
+    Version::parse(tag.trim_start_matches('v')).ok()
 }
 
-fn get_destination_path(url: &GitUrl) -> PathBuf {
+/// The tag with the highest version sharing the major version of `current`, if it is a version.
+fn newest_matching_tag<'a>(current: &str, tags: &[&'a str]) -> Option<&'a str> {
+    let current = parse_tag_version(current)?;
+
+    tags.iter()
+        .filter_map(|tag| parse_tag_version(tag).map(|version| (version, *tag)))
+        .filter(|(version, _)| {
+            version.major == current.major && (current.is_prerelease() || !version.is_prerelease())
+        })
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, tag)| tag)
+}
+
+/// Commits reachable from `to` but not from `from`, newest first. When `dir` is given only
+/// commits changing it relative to their first parent are included.
+pub fn commits_between(
+    repo: &Repository,
+    from: &str,
+    to: Oid,
+    dir: Option<&Path>,
+) -> Result<Vec<CommitInfo>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(to)?;
+    revwalk.hide(repo.revparse_single(from)?.peel_to_commit()?.id())?;
+
+    let mut commits = Vec::new();
+    for id in revwalk {
+        let commit = repo.find_commit(id?)?;
+        if let Some(dir) = dir {
+            let tree_id = |commit: &git2::Commit| -> Result<Option<Oid>> {
+                Ok(commit.tree()?.get_path(dir).ok().map(|entry| entry.id()))
+            };
+            let parent_tree_id = match commit.parents().next() {
+                Some(parent) => tree_id(&parent)?,
+                None => None,
+            };
+            if tree_id(&commit)? == parent_tree_id {
+                continue;
+            }
+        }
+        commits.push(CommitInfo::from_commit(&commit));
+    }
+
+    Ok(commits)
+}
+
+pub fn commit_info(repo: &Repository, revision: &str) -> Result<CommitInfo> {
+    let commit = repo.revparse_single(revision)?.peel_to_commit()?;
+    Ok(CommitInfo::from_commit(&commit))
+}
+
+/// Where the clone of `url` is cached: a readable `<host>/<path>` suffixed with a hash of the
+/// canonical url. Spellings of one url, differing only in case, default port or a trailing
+/// slash, share a clone, while urls which merely read the same, such as those differing in
+/// punctuation in the host, get clones of their own.
+fn get_destination_path(cache_dir: &Path, url: &GitUrl) -> PathBuf {
+    let url = url.canonical();
+    let readable = legacy_destination_path(cache_dir, &url);
+    let name = readable
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    readable.with_file_name(format!(
+        "{}-{:016x}",
+        name,
+        util::fnv1a(url.as_str().as_bytes())
+    ))
+}
+
+/// Where clones were cached before paths included a hash of the url.
+fn legacy_destination_path(cache_dir: &Path, url: &GitUrl) -> PathBuf {
     let host = util::to_alpha_num(&url.host());
-    let mut destination_path = crate::REPOS_CACHE_DIRECTORY.clone();
+    let mut destination_path = cache_dir.to_path_buf();
     destination_path.push(&host);
     destination_path.push(url.path());
     destination_path
 }
 
+/// Moves a clone of `url` cached at its legacy path to `destination_path`. Clones at the
+/// legacy path of a different url, which share it by collision, are left for cleanup.
+fn migrate_legacy_clone(cache_dir: &Path, url: &GitUrl, destination_path: &Path) -> Result<()> {
+    let legacy = legacy_destination_path(cache_dir, url);
+    if destination_path.exists() || !legacy.join(".git").is_dir() {
+        return Ok(());
+    }
+
+    let _lock = lock_clone(url, &legacy, lock_timeout())?;
+    let origin = Repository::open(&legacy)
+        .ok()
+        .and_then(|repo| repo.find_remote("origin").ok()?.url().map(str::to_owned));
+    let same_url = origin
+        .and_then(|origin| GitUrl::from_str(&origin).ok())
+        .is_some_and(|origin| origin.canonical() == url.canonical());
+    if same_url {
+        log::info!(
+            "Moving cached clone of {} from {} to {}",
+            url,
+            legacy.display(),
+            destination_path.display()
+        );
+        fs::rename(&legacy, destination_path)?;
+    }
+    Ok(())
+}
+
 fn clone<P: AsRef<Path>>(url: &str, clone_dir: P, branch: &str) -> Result<Repository> {
     fs::create_dir_all(&clone_dir)?;
     commands::clone(&clone_dir, url, branch)?;
@@ -112,8 +412,253 @@ fn reset_local_repo_to_commit<P: AsRef<Path>>(
     Ok(repo)
 }
 
+/// URL of the repository containing `location`, preferring the default remote reported by
+/// `git ls-remote` and then trying each configured remote in turn.
 pub fn get_repo_from_dir(location: &Path) -> Result<GitUrl> {
-    let url = commands::get_remote_url(location)?;
-    let url = GitUrl::from_str(&url)?;
-    Ok(url)
+    if let Ok(url) = commands::get_remote_url(location) {
+        if let Ok(url) = GitUrl::from_str(&url) {
+            return Ok(url);
+        }
+        log::debug!("Default remote url {} is not a valid git url", url);
+    }
+
+    let repo = Repository::discover(location)?;
+    for name in repo.remotes()?.iter().flatten() {
+        let remote = repo.find_remote(name)?;
+        match remote.url().map(GitUrl::from_str) {
+            Some(Ok(url)) => return Ok(url),
+            _ => log::debug!("Remote {} does not have a valid git url", name),
+        }
+    }
+
+    Err(format_err!(
+        "No git remote with a valid url found for {}",
+        location.display()
+    ))
+}
+
+/// Contents of every .proto file under `dir` at the given revision, relative to `dir`.
+/// Files are read from the object database, so the working tree is left untouched.
+pub fn read_protos_at(
+    repo: &Repository,
+    revision: &str,
+    dir: &Path,
+) -> Result<Vec<(PathBuf, String)>> {
+    let tree = repo.revparse_single(revision)?.peel_to_tree()?;
+    let tree = match tree.get_path(dir) {
+        Ok(entry) => entry.to_object(repo)?.peel_to_tree()?,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut blobs = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        let name = entry.name().unwrap_or_default();
+        if entry.kind() == Some(ObjectType::Blob) && name.ends_with(".proto") {
+            blobs.push((PathBuf::from(root).join(name), entry.id()));
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    blobs
+        .into_iter()
+        .map(|(path, id)| {
+            let blob = repo.find_blob(id)?;
+            Ok((path, String::from_utf8_lossy(blob.content()).into_owned()))
+        })
+        .collect()
+}
+
+/// Like `read_protos_at`, for `dir` within the local repository containing `project_root`.
+pub fn read_local_protos_at(
+    project_root: &Path,
+    revision: &str,
+    dir: &Path,
+) -> Result<Vec<(PathBuf, String)>> {
+    let repo = Repository::discover(project_root)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| format_err!("{} is a bare repository", repo.path().display()))?
+        .canonicalize()?;
+    let relative_root = project_root.canonicalize()?;
+    let relative_root = relative_root.strip_prefix(&workdir)?;
+    read_protos_at(&repo, revision, &relative_root.join(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newest_tag_with_same_major_version_is_found() {
+        let tags = vec![
+            "v1.0.0",
+            "v1.2.0",
+            "v1.10.0-beta",
+            "v2.0.0",
+            "latest",
+            "v1.3.1",
+        ];
+
+        assert_eq!(Some("v1.3.1"), newest_matching_tag("v1.2.0", &tags));
+        assert_eq!(Some("v2.0.0"), newest_matching_tag("v2.0.0", &tags));
+        assert_eq!(None, newest_matching_tag("latest", &tags));
+    }
+
+    fn commit_file(repo: &Repository, path: &str, contents: &str, message: &str) -> Oid {
+        let workdir = repo.workdir().unwrap();
+        fs::create_dir_all(workdir.join(path).parent().unwrap()).unwrap();
+        fs::write(workdir.join(path), contents).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(path)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Jane Doe", "jane@example.com").unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_commits_between_are_filtered_by_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let first = commit_file(&repo, "proto/org/repo/a.proto", "a", "Initial");
+        commit_file(&repo, "README.md", "readme", "Docs");
+        let last = commit_file(&repo, "proto/org/repo/a.proto", "b", "Change a");
+
+        let all = commits_between(&repo, &first.to_string(), last, None).unwrap();
+        let protos = commits_between(
+            &repo,
+            &first.to_string(),
+            last,
+            Some(Path::new("proto/org/repo")),
+        )
+        .unwrap();
+
+        assert_eq!(2, all.len());
+        assert_eq!(1, protos.len());
+        assert_eq!("Change a", protos[0].summary);
+        assert_eq!("Jane Doe", protos[0].author);
+    }
+
+    #[test]
+    fn test_cached_clone_lock_waits_for_holder() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = GitUrl::from_str("git@github.com:org/repo.git").unwrap();
+        let clone_dir = dir.path().join("org/repo");
+        let timeout = Duration::from_millis(300);
+
+        let held = lock_clone(&url, &clone_dir, timeout).unwrap();
+        let error = lock_clone(&url, &clone_dir, timeout).err().unwrap();
+        assert!(error.to_string().starts_with("Timed out"));
+
+        drop(held);
+        assert!(lock_clone(&url, &clone_dir, timeout).is_ok());
+    }
+
+    #[test]
+    fn test_corrupted_clones_are_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        commit_file(&repo, "README.md", "readme", "Initial");
+        assert!(verify_clone(dir.path()).is_err());
+
+        repo.remote("origin", "git@github.com:org/repo.git")
+            .unwrap();
+        assert!(verify_clone(dir.path()).is_ok());
+
+        fs::write(dir.path().join(".git/index.lock"), "").unwrap();
+        assert!(verify_clone(dir.path()).is_err());
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(verify_clone(empty.path()).is_err());
+    }
+
+    #[test]
+    fn test_similar_urls_are_cached_apart() {
+        let url = |s: &str| GitUrl::from_str(s).unwrap();
+        let paths: Vec<PathBuf> = vec![
+            url("git@a.b.com:org/repo.git"),
+            url("git@ab.com:org/repo.git"),
+            url("https://github.com/org/repo.git"),
+            url("git@github.com:org/repo.git"),
+        ]
+        .iter()
+        .map(|url| get_destination_path(Path::new("cache"), url))
+        .collect();
+
+        let distinct: std::collections::BTreeSet<&PathBuf> = paths.iter().collect();
+        assert_eq!(paths.len(), distinct.len());
+        assert!(paths[0]
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("repo-"));
+    }
+
+    #[test]
+    fn test_spellings_of_a_url_share_a_clone() {
+        let path =
+            |s: &str| get_destination_path(Path::new("cache"), &GitUrl::from_str(s).unwrap());
+
+        assert_eq!(
+            path("https://github.com/org/repo.git"),
+            path("https://GitHub.com:443/org/repo.git/")
+        );
+        assert_ne!(
+            path("https://github.com/org/repo.git"),
+            path("https://github.com:8443/org/repo.git")
+        );
+    }
+
+    #[test]
+    fn test_paths_differing_in_case_get_different_clones() {
+        let path =
+            |s: &str| get_destination_path(Path::new("cache"), &GitUrl::from_str(s).unwrap());
+
+        assert_ne!(
+            path("https://example.com/org/repo.git"),
+            path("https://example.com/Org/Repo.git")
+        );
+    }
+
+    #[test]
+    fn test_legacy_clone_is_migrated_when_origin_matches() {
+        let url = GitUrl::from_str("git@github.com:org/migrated.git").unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let legacy = legacy_destination_path(cache_dir.path(), &url);
+        let destination = get_destination_path(cache_dir.path(), &url);
+        let repo = Repository::init(&legacy).unwrap();
+        repo.remote("origin", url.as_str()).unwrap();
+
+        migrate_legacy_clone(cache_dir.path(), &url, &destination).unwrap();
+
+        assert!(destination.join(".git").is_dir());
+        assert!(!legacy.exists());
+    }
+
+    #[test]
+    fn test_colliding_legacy_clone_is_not_migrated() {
+        let url = GitUrl::from_str("git@ab.com:org/collided.git").unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let legacy = legacy_destination_path(cache_dir.path(), &url);
+        let destination = get_destination_path(cache_dir.path(), &url);
+        let repo = Repository::init(&legacy).unwrap();
+        repo.remote("origin", "git@a.b.com:org/collided.git")
+            .unwrap();
+
+        migrate_legacy_clone(cache_dir.path(), &url, &destination).unwrap();
+
+        assert!(!destination.exists());
+        assert!(legacy.join(".git").is_dir());
+    }
 }
@@ -0,0 +1,298 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::{get_destination_path, lock_clone};
+use crate::git_url::GitUrl;
+use crate::{util, Result, REPOS_CACHE_DIRECTORY};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time;
+use walkdir::WalkDir;
+
+/// A clone in the repos cache.
+#[derive(Debug)]
+pub struct CachedClone {
+    pub path: PathBuf,
+    pub size: u64,
+    pub last_used: DateTime<Utc>,
+}
+
+impl CachedClone {
+    /// Path relative to the cache directory, e.g. `githubcom/org/repo`.
+    pub fn name(&self) -> String {
+        self.path
+            .strip_prefix(REPOS_CACHE_DIRECTORY.as_path())
+            .unwrap_or(&self.path)
+            .display()
+            .to_string()
+    }
+}
+
+/// Which clones `cleanup` removes. Clones are removed when they match every criterion given,
+/// so with none all are removed.
+#[derive(Debug, Default)]
+pub struct Criteria {
+    /// Remove clones not used for at least this long.
+    pub stale: Option<Duration>,
+    /// Remove clones other than these.
+    pub keep: Option<BTreeSet<PathBuf>>,
+}
+
+impl Criteria {
+    fn matches(&self, clone: &CachedClone, now: DateTime<Utc>) -> bool {
+        let stale = self
+            .stale
+            .is_none_or(|stale| now.signed_duration_since(clone.last_used) >= stale);
+        let unused = self
+            .keep
+            .as_ref()
+            .is_none_or(|keep| !keep.contains(&clone.path));
+        stale && unused
+    }
+
+    /// Whether no criteria were given, so that the whole cache is being cleared.
+    fn is_empty(&self) -> bool {
+        self.stale.is_none() && self.keep.is_none()
+    }
+}
+
+/// Where the cached clone of `url` lives.
+pub fn clone_path(url: &GitUrl) -> PathBuf {
+    get_destination_path(&REPOS_CACHE_DIRECTORY, url)
+}
+
+/// Every clone in the cache, found by its `.git` directory.
+pub fn cached_clones() -> Result<Vec<CachedClone>> {
+    let mut clones = Vec::new();
+    if !REPOS_CACHE_DIRECTORY.exists() {
+        return Ok(clones);
+    }
+
+    let mut entries = WalkDir::new(REPOS_CACHE_DIRECTORY.as_path())
+        .min_depth(1)
+        .into_iter();
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        if entry.file_type().is_dir() && entry.path().join(".git").is_dir() {
+            clones.push(CachedClone {
+                path: entry.path().to_path_buf(),
+                size: size_of(entry.path()),
+                last_used: last_used(entry.path())?,
+            });
+            entries.skip_current_dir();
+        }
+    }
+
+    Ok(clones)
+}
+
+/// Removes the clones matching `criteria`, logging the space each takes up and what is left.
+/// Clones in use by another protovend process are skipped. Without criteria, whatever else is
+/// left in the cache, such as interrupted clones, is removed as well. Lock files are kept, as a
+/// process waiting on one would otherwise hold a lock no other process can see.
+pub fn remove(criteria: &Criteria, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let clones = cached_clones()?;
+    if clones.is_empty() {
+        log::info!("No repos are cached");
+        if criteria.is_empty() {
+            remove_leftovers(&[], dry_run)?;
+        }
+        return Ok(Vec::new());
+    }
+
+    let now = Utc::now();
+    let mut removed = Vec::new();
+    let mut in_use = Vec::new();
+    let (mut freed, mut kept_size) = (0, 0);
+    for clone in clones.iter() {
+        if !criteria.matches(clone, now) {
+            log::debug!(
+                "Keeping {} ({})",
+                clone.name(),
+                util::format_size(clone.size)
+            );
+            kept_size += clone.size;
+            continue;
+        }
+
+        if dry_run {
+            log::info!(
+                "Would remove {} ({})",
+                clone.name(),
+                util::format_size(clone.size)
+            );
+        } else {
+            let lock = match lock_clone(clone.name(), &clone.path, time::Duration::from_secs(0)) {
+                Ok(lock) => lock,
+                Err(_) => {
+                    log::warn!(
+                        "Skipping {} as another protovend process is using it",
+                        clone.name()
+                    );
+                    kept_size += clone.size;
+                    in_use.push(clone.path.clone());
+                    continue;
+                }
+            };
+            fs::remove_dir_all(&clone.path)?;
+            drop(lock);
+            log::info!(
+                "Removed {} ({})",
+                clone.name(),
+                util::format_size(clone.size)
+            );
+        }
+        freed += clone.size;
+        removed.push(clone.path.clone());
+    }
+
+    log::info!(
+        "{} {} from {} repo(s); {} repo(s) using {} kept",
+        if dry_run { "Would free" } else { "Freed" },
+        util::format_size(freed),
+        removed.len(),
+        clones.len() - removed.len(),
+        util::format_size(kept_size)
+    );
+
+    if criteria.is_empty() {
+        if dry_run {
+            in_use = clones.iter().map(|clone| clone.path.clone()).collect();
+        }
+        remove_leftovers(&in_use, dry_run)?;
+    }
+
+    Ok(removed)
+}
+
+/// Removes everything in the cache other than the clones in use and lock files, such as
+/// directories left by interrupted clones. Clones whose lock is held by another protovend
+/// process, which may be cloning, are kept.
+fn remove_leftovers(in_use: &[PathBuf], dry_run: bool) -> Result<()> {
+    if !REPOS_CACHE_DIRECTORY.exists() {
+        return Ok(());
+    }
+
+    let mut kept: BTreeSet<PathBuf> = in_use.iter().cloned().collect();
+    let mut locks = Vec::new();
+    if !dry_run {
+        let lock_files = WalkDir::new(REPOS_CACHE_DIRECTORY.as_path())
+            .into_iter()
+            .filter_entry(|entry| !kept.contains(entry.path()))
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| clone_of_lock_file(entry.path()))
+            .collect::<Vec<_>>();
+        for clone in lock_files {
+            match lock_clone(clone.display(), &clone, time::Duration::from_secs(0)) {
+                Ok(lock) => locks.push(lock),
+                Err(_) => {
+                    kept.insert(clone);
+                }
+            }
+        }
+    }
+
+    let (mut count, mut size) = (0, 0);
+    for entry in WalkDir::new(REPOS_CACHE_DIRECTORY.as_path())
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_entry(|entry| {
+            !kept.contains(entry.path()) && clone_of_lock_file(entry.path()).is_none()
+        })
+    {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            if !dry_run && fs::read_dir(entry.path())?.next().is_none() {
+                fs::remove_dir(entry.path())?;
+            }
+            continue;
+        }
+
+        log::debug!("Removing leftover {}", entry.path().display());
+        count += 1;
+        size += entry.metadata()?.len();
+        if !dry_run {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    drop(locks);
+
+    if count > 0 {
+        log::info!(
+            "{} {} leftover file(s) using {}",
+            if dry_run { "Would remove" } else { "Removed" },
+            count,
+            util::format_size(size)
+        );
+    }
+    Ok(())
+}
+
+/// The clone a lock file belongs to, if `path` is one.
+fn clone_of_lock_file(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let clone = name.strip_prefix('.')?.strip_suffix(".lock")?;
+    Some(path.with_file_name(clone))
+}
+
+fn size_of(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// When the clone was last locked for use, falling back to when its directory last changed
+/// for clones made before uses were recorded.
+fn last_used(path: &Path) -> Result<DateTime<Utc>> {
+    let lock_path = util::staging_path(path, "lock");
+    let metadata = fs::metadata(&lock_path).or_else(|_| fs::metadata(path))?;
+    Ok(DateTime::from(metadata.modified()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clone(path: &str, days_unused: i64, now: DateTime<Utc>) -> CachedClone {
+        CachedClone {
+            path: PathBuf::from(path),
+            size: 0,
+            last_used: now - Duration::days(days_unused),
+        }
+    }
+
+    #[test]
+    fn test_clones_must_match_every_criterion() {
+        let now = Utc::now();
+        let criteria = Criteria {
+            stale: Some(Duration::days(30)),
+            keep: Some(vec![PathBuf::from("kept")].into_iter().collect()),
+        };
+
+        assert!(criteria.matches(&clone("other", 40, now), now));
+        assert!(!criteria.matches(&clone("other", 10, now), now));
+        assert!(!criteria.matches(&clone("kept", 40, now), now));
+        assert!(Criteria::default().matches(&clone("kept", 0, now), now));
+    }
+}
@@ -39,6 +39,28 @@ pub fn fetch<P: AsRef<Path>>(cwd: P, branch_name: &str, remote_name: &str) -> Re
     }
 }
 
+/// Fetches every branch and tag from the remote without touching the working tree.
+pub fn fetch_all<P: AsRef<Path>>(cwd: P, remote_name: &str) -> Result<()> {
+    let status = Command::new("git")
+        .current_dir(cwd)
+        .arg("fetch")
+        .arg("--tags")
+        .arg(remote_name)
+        .arg(format!("+refs/heads/*:refs/remotes/{}/*", remote_name))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "Git fetch failed with code {:?}",
+            status.code()
+        ))
+    }
+}
+
 pub fn clone<P: AsRef<Path>>(cwd: P, url: &str, branch: &str) -> Result<()> {
     let output = Command::new("git")
         .current_dir(cwd)
@@ -68,7 +90,7 @@ pub fn get_remote_url<P: AsRef<Path>>(cwd: P) -> Result<String> {
         .output()?;
 
     if output.status.success() {
-        Ok(String::from_utf8(output.stdout)?)
+        Ok(String::from_utf8(output.stdout)?.trim().to_owned())
     } else {
         Err(format_err!(
             "Git ls-remote failed with code {:?}",
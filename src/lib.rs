@@ -14,21 +14,29 @@
  * limitations under the License.
 */
 
-use failure::Error;
+use failure::{format_err, Error};
 use lazy_static::lazy_static;
 use semver::Version;
+use std::collections::BTreeSet;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 
+mod breaking;
 mod check;
 mod config;
 mod date_compat;
 mod git;
 pub mod git_url;
 mod lock;
+mod proto;
+pub mod summary;
 mod util;
 
+pub use check::OutputFormat;
+pub use lock::graph::GraphFormat;
+use summary::{DependencyChange, Summary};
+
 lazy_static! {
     static ref CRATE_VERSION: Version = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
     static ref REPOS_CACHE_DIRECTORY: PathBuf = env::temp_dir().join(".protovend/repos");
@@ -37,28 +45,60 @@ lazy_static! {
 
 pub type Result<A> = std::result::Result<A, Error>;
 
-pub fn init() -> Result<()> {
-    config::init()?;
-    lock::init()
+pub fn init() -> Result<Summary> {
+    let mut summary = Summary::default();
+    if config::init()? {
+        summary.files_written.push(config::PROTOVEND_YAML.clone());
+    }
+    if lock::init()? {
+        summary.files_written.push(lock::PROTOVEND_LOCK.clone());
+    }
+    Ok(summary)
 }
 
-pub fn add(url: git_url::GitUrl, branch: String) -> Result<()> {
+pub fn add(url: git_url::GitUrl, branch: String, transitive: bool) -> Result<Summary> {
     let mut config = config::get_config()?;
 
-    config.add_dependency(url, branch)
+    let mut summary = Summary::default();
+    if config.add_dependency(url.clone(), branch.clone(), transitive)? {
+        summary.dependencies.push(DependencyChange {
+            url,
+            branch,
+            old_commit: None,
+            new_commit: None,
+        });
+        summary.files_written.push(config::PROTOVEND_YAML.clone());
+    }
+    Ok(summary)
 }
 
-pub fn install() -> Result<()> {
+pub fn install() -> Result<Summary> {
     let config = config::get_config()?;
     let mut lock = lock::load_lock()?;
-    lock.update_imports(config)?;
-    lock.vendor().map(|_| log_blurb())
+    let previous = lock.clone();
+
+    let mut summary = Summary::default();
+    let changed = lock.resolve_imports(config)?;
+    let vendored = lock.vendor(changed)?;
+    if changed {
+        summary.files_written.push(lock::PROTOVEND_LOCK.clone());
+    }
+    summary.dependencies = lock.changes_since(&previous);
+    summary.files_written.extend(vendored);
+    log_blurb();
+    Ok(summary)
 }
 
 //FIXME consider doing some sort of matching here?
-pub fn update(url: Option<git_url::GitUrl>) -> Result<()> {
+pub fn update(
+    url: Option<git_url::GitUrl>,
+    deny_breaking: bool,
+    dry_run: bool,
+    changelog: Option<PathBuf>,
+) -> Result<Summary> {
     let config = config::get_config()?;
     let mut lock = lock::load_lock()?;
+    let previous = lock.clone();
 
     if let Some(repo) = url {
         lock.clear_imports(repo);
@@ -66,18 +106,179 @@ pub fn update(url: Option<git_url::GitUrl>) -> Result<()> {
         lock.clear_all_imports();
     }
 
-    lock.update_imports(config)?;
-    lock.vendor().map(|_| log_blurb())
+    lock.resolve_imports(config)?;
+
+    let breaking_changes = lock.breaking_changes_since(&previous)?;
+    if deny_breaking && !breaking_changes.is_empty() {
+        return Err(format_err!(
+            "Refusing to update as {} breaking change(s) were found",
+            breaking_changes.len()
+        ));
+    }
+
+    let mut summary = if dry_run {
+        Summary {
+            dependencies: lock.log_vendored_diff(&previous)?,
+            ..Summary::default()
+        }
+    } else {
+        let vendored = lock.vendor(true)?;
+
+        let mut summary = Summary {
+            dependencies: lock.changes_since(&previous),
+            files_written: vec![lock::PROTOVEND_LOCK.clone()],
+            ..Summary::default()
+        };
+        summary.files_written.extend(vendored);
+        log_blurb();
+        summary
+    };
+
+    if let Some(path) = changelog {
+        util::write_atomic(&path, lock::changelog(&summary.dependencies)?)?;
+        log::info!("Wrote changelog to {}", path.display());
+        summary.files_written.push(path);
+    }
+
+    Ok(summary)
+}
+
+/// Lists dependencies with newer commits than those locked, without changing anything.
+pub fn outdated() -> Result<Summary> {
+    let lock = lock::load_lock()?;
+    let dependencies = lock.outdated()?;
+    Ok(Summary {
+        outdated: !dependencies.is_empty(),
+        dependencies,
+        ..Summary::default()
+    })
+}
+
+pub fn list() -> Result<Summary> {
+    let config = config::get_config()?;
+    let lock = lock::load_lock()?;
+    Ok(Summary {
+        listed: lock.list(&config),
+        ..Summary::default()
+    })
 }
 
-pub fn cleanup() -> Result<()> {
-    fs::remove_dir_all(REPOS_CACHE_DIRECTORY.as_path())?;
-    Ok(())
+pub fn tree() -> Result<Summary> {
+    let lock = lock::load_lock()?;
+    Ok(Summary {
+        tree: lock.tree()?,
+        ..Summary::default()
+    })
 }
 
-pub fn lint() -> Result<()> {
+pub fn why(query: String) -> Result<Summary> {
+    let lock = lock::load_lock()?;
+    Ok(Summary {
+        traced: lock.why(&query)?,
+        ..Summary::default()
+    })
+}
+
+/// Prints the dependency graph of the projects at `paths`, or the current directory.
+pub fn graph(paths: Vec<PathBuf>, format: GraphFormat) -> Result<Summary> {
+    let paths = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths
+    };
+
+    let mut graph = lock::graph::Graph::default();
+    for path in paths.iter() {
+        let in_project = |e: Error| format_err!("{}: {}", path.display(), e);
+        let config = config::load_config(&path.join(config::PROTOVEND_YAML.as_path()))
+            .map_err(in_project)?;
+        let lock =
+            lock::load_lockfile(&path.join(lock::PROTOVEND_LOCK.as_path())).map_err(in_project)?;
+
+        let name = match &config.project_url {
+            Some(url) => url.to_string(),
+            None => fs::canonicalize(path)?
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+        };
+        graph.add_project(&name, path, &config, &lock)?;
+    }
+
+    println!("{}", graph.render(format));
+    Ok(Summary::default())
+}
+
+pub fn scan(dirs: Vec<PathBuf>, producer: Option<git_url::GitUrl>, fetch: bool) -> Result<Summary> {
+    Ok(Summary {
+        consumers: lock::scan(&dirs, producer.as_ref(), fetch)?,
+        ..Summary::default()
+    })
+}
+
+/// Removes cached repos: all of them, or those not used for `stale` and, with `unused`, those
+/// not locked by any of `projects`.
+pub fn cleanup(
+    stale: Option<String>,
+    unused: bool,
+    projects: Vec<PathBuf>,
+    dry_run: bool,
+) -> Result<Summary> {
+    let mut criteria = git::cache::Criteria {
+        stale: stale.map(|s| util::parse_duration(&s)).transpose()?,
+        keep: None,
+    };
+
+    if unused {
+        let projects = if projects.is_empty() {
+            vec![PathBuf::from(".")]
+        } else {
+            projects
+        };
+        let mut keep = BTreeSet::new();
+        for project in projects.iter() {
+            let lock = lock::load_lockfile(&project.join(lock::PROTOVEND_LOCK.as_path()))
+                .map_err(|e| format_err!("{}: {}", project.display(), e))?;
+            keep.extend(lock.urls().map(git::cache::clone_path));
+        }
+        criteria.keep = Some(keep);
+    }
+
+    git::cache::remove(&criteria, dry_run)?;
+    Ok(Summary::default())
+}
+
+pub fn lint(
+    url: Option<git_url::GitUrl>,
+    breaking_against: Option<String>,
+    format: OutputFormat,
+    fix: bool,
+    dry_run: bool,
+) -> Result<Summary> {
     let cwd = env::current_dir()?;
-    check::run_checks(&cwd, &git::get_repo_from_dir(cwd.as_path())?)
+    let options = check::LintOptions {
+        breaking_against,
+        format,
+        fix,
+        dry_run,
+        check_vendored: true,
+        check_style: true,
+    };
+
+    let url = match url {
+        Some(url) => Some(url),
+        None => match config::load_project_url(&cwd)? {
+            Some(url) => Some(url),
+            None => git::get_repo_from_dir(&cwd)
+                .map_err(|e| log::debug!("Unable to find project url from git: {}", e))
+                .ok(),
+        },
+    };
+
+    Ok(Summary {
+        lint_results: check::run_lint(&cwd, url.as_ref(), &options)?,
+        ..Summary::default()
+    })
 }
 
 fn log_blurb() {
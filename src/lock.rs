@@ -14,22 +14,33 @@
  * limitations under the License.
 */
 
+use crate::breaking::{self, BreakingChange, Schema};
 use crate::config::Dependency;
 use crate::config::ProtovendConfig;
 use crate::git;
 use crate::git_url::{GitUrl, Host, Repo};
+use crate::summary::{DependencyChange, ListedDependency, TracedProto, TreeDependency};
 use crate::util;
-use crate::{date_compat, Result};
+use crate::{date_compat, Result, PROTOS_DIRECTORY};
 use chrono::{Local, NaiveDateTime};
 use failure::format_err;
 use lazy_static::lazy_static;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use serde_yaml;
-use std::fs::File;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+mod changelog;
+mod diff;
+pub mod graph;
+mod imports;
+mod inspect;
+mod outdated;
+mod scan;
+mod transitive;
 pub mod vendor;
 
 #[cfg(test)]
@@ -45,6 +56,10 @@ struct Import {
     branch: String,
     commit: String,
     url: GitUrl,
+    #[serde(default, skip_serializing_if = "util::is_false")]
+    transitive: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    via: Vec<GitUrl>,
 }
 
 #[derive(Deserialize)]
@@ -63,17 +78,22 @@ impl From<GithubImport> for Import {
             url,
             branch: import.branch,
             commit: import.commit,
+            transitive: false,
+            via: Vec::new(),
         }
     }
 }
 
 impl PartialEq<Dependency> for Import {
     fn eq(&self, other: &Dependency) -> bool {
-        self.url == other.url && self.branch == other.branch
+        self.via.is_empty()
+            && self.url == other.url
+            && self.branch == other.branch
+            && self.transitive == other.transitive
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ProtovendLock {
     imports: Vec<Import>,
     min_protovend_version: Version,
@@ -110,22 +130,56 @@ enum Lock {
     Legacy(LegacyProtovendLock),
 }
 
+/// The contents of every proto vendored for the locked imports, keyed by their path relative
+/// to the output directory, and the url and commit of each import whose locked commit is not
+/// cached, as its files cannot be known.
+pub struct CachedFiles<'a> {
+    pub files: BTreeMap<PathBuf, String>,
+    pub uncached: Vec<(&'a GitUrl, &'a str)>,
+}
+
 impl ProtovendLock {
-    pub fn vendor(&self) -> Result<()> {
-        vendor::prepare_output_directory()?;
+    /// Vendors every import and, with `write_lock`, writes the lock as the vendored protos are
+    /// swapped in, so that a failure leaves the previous protos and lock in place together.
+    pub fn vendor(&mut self, write_lock: bool) -> Result<Vec<PathBuf>> {
+        let mut plan = vendor::VendorPlan::default();
 
         for import in self.imports.iter() {
-            vendor::vendor_import(import)?;
+            plan.add_import(import)?;
         }
 
-        Ok(())
+        plan.check_conflicts()?;
+
+        let written = if write_lock {
+            let staged_lock = self.stage()?;
+            let result = plan.write(|| Ok(fs::rename(&staged_lock, PROTOVEND_LOCK.as_path())?));
+            if result.is_err() {
+                let _ = fs::remove_file(&staged_lock);
+            }
+            result?
+        } else {
+            plan.write(|| Ok(()))?
+        };
+
+        imports::report_unresolved(&plan);
+
+        Ok(written)
+    }
+
+    pub fn write(&mut self) -> Result<()> {
+        let staged = self.stage()?;
+        let result = fs::rename(&staged, PROTOVEND_LOCK.as_path());
+        if result.is_err() {
+            let _ = fs::remove_file(&staged);
+        }
+        Ok(result?)
     }
 
-    fn write(&mut self) -> Result<()> {
-        let f = File::create(PROTOVEND_LOCK.as_path())?;
+    /// Writes the lock beside the lock file, returning the path to rename over it.
+    fn stage(&mut self) -> Result<PathBuf> {
         self.imports.sort_by(|a, b| a.url.cmp(&b.url));
         self.updated = Local::now().naive_local();
-        Ok(serde_yaml::to_writer(f, &self)?)
+        util::write_staged(PROTOVEND_LOCK.as_path(), serde_yaml::to_string(&self)?)
     }
 
     fn process_new_imports(&self, deps: Vec<Dependency>) -> Result<Vec<Import>> {
@@ -134,17 +188,215 @@ impl ProtovendLock {
         let new_entries: Result<Vec<Import>> = added_entries.into_iter().map(to_import).collect();
         entries.append(&mut new_entries?);
 
-        Ok(entries)
+        transitive::resolve(entries, &self.imports, transitive::producer_imports)
     }
 
-    pub fn update_imports(&mut self, config: ProtovendConfig) -> Result<()> {
+    /// Brings imports in line with the config without writing the lock, returning whether
+    /// anything changed.
+    pub fn resolve_imports(&mut self, config: ProtovendConfig) -> Result<bool> {
         let new_imports = self.process_new_imports(config.vendor)?;
         if new_imports != self.imports {
             self.imports = new_imports;
-            self.write()
+            Ok(true)
         } else {
-            Ok(())
+            Ok(false)
+        }
+    }
+
+    /// Compares the schemas of every import that has moved to a new commit since `previous`,
+    /// logging any changes that would break existing consumers.
+    pub fn breaking_changes_since(&self, previous: &ProtovendLock) -> Result<Vec<BreakingChange>> {
+        let mut all_changes = Vec::new();
+
+        for import in self.imports.iter() {
+            let old = previous
+                .imports
+                .iter()
+                .find(|old| old.url == import.url && old.branch == import.branch);
+            let old_commit = match old {
+                Some(old) if old.commit != import.commit => &old.commit,
+                _ => continue,
+            };
+
+            let repo = git::get_repo(&import.url, &import.branch, &import.commit)?;
+            let proto_dir = PROTOS_DIRECTORY.join(import.url.sanitised_path());
+
+            let old_protos = match git::read_protos_at(&repo, old_commit, &proto_dir) {
+                Ok(protos) => protos,
+                Err(e) => {
+                    log::warn!(
+                        "Unable to check {} for breaking changes as locked commit {} cannot be read: {}",
+                        import.url,
+                        old_commit,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let new_protos = git::read_protos_at(&repo, &import.commit, &proto_dir)?;
+
+            let changes = breaking::compare(&Schema::parse(old_protos), &Schema::parse(new_protos));
+
+            if changes.is_empty() {
+                log::info!(
+                    "No breaking changes in {} between {} and {}",
+                    import.url,
+                    old_commit,
+                    import.commit
+                );
+            } else {
+                log::warn!(
+                    "{} breaking change(s) in {} between {} and {}:",
+                    changes.len(),
+                    import.url,
+                    old_commit,
+                    import.commit
+                );
+                for change in changes.iter() {
+                    log::warn!("  {}", change);
+                }
+            }
+
+            all_changes.extend(changes);
+        }
+
+        Ok(all_changes)
+    }
+
+    /// The protos vendored for the imports, read from the cached clones without fetching.
+    pub fn cached_files(&self) -> Result<CachedFiles<'_>> {
+        let mut files = BTreeMap::new();
+        let mut uncached = Vec::new();
+
+        for import in self.imports.iter() {
+            let repo = match git::cached_repo(&import.url)? {
+                Some(repo) if repo.revparse_single(&import.commit).is_ok() => repo,
+                _ => {
+                    uncached.push((&import.url, import.commit.as_str()));
+                    continue;
+                }
+            };
+            let sanitised_path = import.url.sanitised_path();
+            let proto_dir = PROTOS_DIRECTORY.join(&sanitised_path);
+
+            for (relative, contents) in git::read_protos_at(&repo, &import.commit, &proto_dir)? {
+                files.insert(Path::new(&sanitised_path).join(relative), contents);
+            }
+        }
+
+        Ok(CachedFiles { files, uncached })
+    }
+
+    /// Describes each way the direct imports differ from the dependencies in the config.
+    pub fn differences_from(&self, config: &ProtovendConfig) -> Vec<String> {
+        let direct: Vec<&Import> = self.imports.iter().filter(|i| i.via.is_empty()).collect();
+        let mut differences = Vec::new();
+
+        for dep in config.vendor.iter() {
+            if !direct.iter().any(|import| *import == dep) {
+                differences.push(format!(
+                    "{} ({} branch) is not locked, or is locked with different settings",
+                    dep.url, dep.branch
+                ));
+            }
+        }
+        for import in direct {
+            if !config.vendor.iter().any(|dep| import == dep) {
+                differences.push(format!(
+                    "{} ({} branch) is locked but is not a dependency",
+                    import.url, import.branch
+                ));
+            }
+        }
+
+        differences
+    }
+
+    /// Logs the changes to vendored protos that updating to these imports would make.
+    pub fn log_vendored_diff(&self, previous: &ProtovendLock) -> Result<Vec<DependencyChange>> {
+        let changes = self.changes_since(previous);
+
+        if changes.is_empty() {
+            log::info!("All dependencies are up to date");
+        }
+
+        for change in changes.iter() {
+            match (&change.old_commit, &change.new_commit) {
+                (Some(old), Some(new)) => {
+                    log::info!("{} would move from {} to {}", change.url, old, new)
+                }
+                (None, Some(new)) => log::info!("{} would be added at {}", change.url, new),
+                (Some(old), None) => log::info!("{} would be removed from {}", change.url, old),
+                (None, None) => {}
+            }
+
+            let diff = diff::vendored_diff(change)?;
+            if diff.is_empty() {
+                log::info!("No vendored protos would change for {}", change.url);
+            } else {
+                log::info!("Vendored protos would change for {}:\n{}", change.url, diff);
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Direct imports with newer commits available on their branch.
+    pub fn outdated(&self) -> Result<Vec<DependencyChange>> {
+        outdated::find(&self.imports)
+    }
+
+    /// Every locked repo, including those vendored transitively.
+    pub fn urls(&self) -> impl Iterator<Item = &GitUrl> {
+        self.imports.iter().map(|import| &import.url)
+    }
+
+    /// Logs and returns each dependency with its locked commit, vendored file count and sync
+    /// status.
+    pub fn list(&self, config: &ProtovendConfig) -> Vec<ListedDependency> {
+        inspect::list(&self.imports, config, self.updated)
+    }
+
+    /// Logs and returns the files vendored for each import and the dependencies their imports
+    /// refer to.
+    pub fn tree(&self) -> Result<Vec<TreeDependency>> {
+        inspect::tree(&self.imports)
+    }
+
+    /// Logs and returns where the vendored files matching a file, package or message name came
+    /// from.
+    pub fn why(&self, query: &str) -> Result<Vec<TracedProto>> {
+        inspect::why(&self.imports, query)
+    }
+
+    /// Imports added, removed or moved to a new commit since `previous`.
+    pub fn changes_since(&self, previous: &ProtovendLock) -> Vec<DependencyChange> {
+        let same = |a: &Import, b: &Import| a.url == b.url && a.branch == b.branch;
+        let mut changes = Vec::new();
+
+        for import in self.imports.iter() {
+            let old = previous.imports.iter().find(|old| same(old, import));
+            if old.map(|old| &old.commit) != Some(&import.commit) {
+                changes.push(DependencyChange {
+                    url: import.url.clone(),
+                    branch: import.branch.clone(),
+                    old_commit: old.map(|old| old.commit.clone()),
+                    new_commit: Some(import.commit.clone()),
+                });
+            }
         }
+        for old in previous.imports.iter() {
+            if !self.imports.iter().any(|import| same(old, import)) {
+                changes.push(DependencyChange {
+                    url: old.url.clone(),
+                    branch: old.branch.clone(),
+                    old_commit: Some(old.commit.clone()),
+                    new_commit: None,
+                });
+            }
+        }
+
+        changes
     }
 
     pub fn clear_all_imports(&mut self) {
@@ -152,17 +404,25 @@ impl ProtovendLock {
     }
 
     pub fn clear_imports(&mut self, repo: GitUrl) {
-        self.imports.retain(|import| import.url != repo)
+        self.imports
+            .retain(|import| import.url != repo && !import.via.contains(&repo))
     }
 }
 
+/// Markdown listing the upstream commits behind each change, from the cached clones.
+pub fn changelog(changes: &[DependencyChange]) -> Result<String> {
+    changelog::render(changes)
+}
+
+pub use scan::scan;
+
 pub fn load_lock() -> Result<ProtovendLock> {
     load_lockfile(&PROTOVEND_LOCK)
 }
 
-fn load_lockfile(lock_file: &PathBuf) -> Result<ProtovendLock> {
+pub(crate) fn load_lockfile(lock_file: &Path) -> Result<ProtovendLock> {
     if lock_file.exists() {
-        let f = File::open(lock_file.as_path())?;
+        let f = File::open(lock_file)?;
         let lock: Lock = serde_yaml::from_reader(f)?;
 
         let lock: ProtovendLock = match lock {
@@ -189,13 +449,16 @@ fn to_import(dep: Dependency) -> Result<Import> {
         commit: git::get_latest_commit_sha(&dep.url, &dep.branch)?.to_string(),
         branch: dep.branch,
         url: dep.url,
+        transitive: dep.transitive,
+        via: Vec::new(),
     })
 }
 
-fn diff_lock(
-    mut deps: Vec<Dependency>,
-    mut imports: Vec<Import>,
-) -> (Vec<Import>, Vec<Dependency>) {
+fn diff_lock(mut deps: Vec<Dependency>, imports: Vec<Import>) -> (Vec<Import>, Vec<Dependency>) {
+    let mut imports: Vec<Import> = imports
+        .into_iter()
+        .filter(|import| import.via.is_empty())
+        .collect();
     let mut retained_imports = Vec::new();
     deps.retain(|dep| {
         if let Some(position) = imports.iter().position(|import| import == dep) {
@@ -209,21 +472,23 @@ fn diff_lock(
     (retained_imports, deps)
 }
 
-pub fn init() -> Result<()> {
+/// Creates the lock if it does not exist, returning whether it was created.
+pub fn init() -> Result<bool> {
     if PROTOVEND_LOCK.exists() {
         log::warn!(
             "{} file already exists in project",
             PROTOVEND_LOCK.to_string_lossy()
         );
-        Ok(())
+        Ok(false)
     } else {
         let mut lock = ProtovendLock {
             imports: Vec::new(),
             min_protovend_version: crate::CRATE_VERSION.clone(),
             updated: Local::now().naive_local(),
         };
-        lock.write()
-            .map(|_| log::info!("Created {}", PROTOVEND_LOCK.as_path().to_string_lossy()))
+        lock.write()?;
+        log::info!("Created {}", PROTOVEND_LOCK.as_path().to_string_lossy());
+        Ok(true)
     }
 }
 
@@ -231,6 +496,96 @@ pub fn init() -> Result<()> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_differences_from_config_are_described() {
+        let locked = Import {
+            branch: String::from("master"),
+            commit: String::from("abc"),
+            url: GitUrl::from_str("git@github.com:org/locked.git").unwrap(),
+            transitive: false,
+            via: Vec::new(),
+        };
+        let mut transitive = locked.clone();
+        transitive.url = GitUrl::from_str("git@github.com:org/transitive.git").unwrap();
+        transitive.via = vec![locked.url.clone()];
+        let lock = ProtovendLock {
+            min_protovend_version: Version::parse("0.0.0").unwrap(),
+            imports: vec![locked, transitive],
+            updated: Local::now().naive_local(),
+        };
+        let config = ProtovendConfig {
+            min_protovend_version: Version::parse("0.0.0").unwrap(),
+            vendor: vec![Dependency {
+                url: GitUrl::from_str("git@github.com:org/locked.git").unwrap(),
+                branch: String::from("develop"),
+                transitive: false,
+            }],
+            project_url: None,
+            lint: Default::default(),
+        };
+
+        assert_eq!(
+            vec![
+                "git@github.com:org/locked.git (develop branch) is not locked, or is locked with different settings",
+                "git@github.com:org/locked.git (master branch) is locked but is not a dependency"
+            ],
+            lock.differences_from(&config)
+        );
+    }
+
+    #[test]
+    fn test_changes_since_previous_lock() {
+        let import = |url: &str, commit: &str| Import {
+            branch: String::from("master"),
+            commit: String::from(commit),
+            url: GitUrl::from_str(url).unwrap(),
+            transitive: false,
+            via: Vec::new(),
+        };
+        let lock = |imports| ProtovendLock {
+            min_protovend_version: Version::parse("0.0.0").unwrap(),
+            imports,
+            updated: Local::now().naive_local(),
+        };
+        let previous = lock(vec![
+            import("git@github.com:org/moved.git", "a"),
+            import("git@github.com:org/removed.git", "b"),
+            import("git@github.com:org/unchanged.git", "c"),
+        ]);
+        let current = lock(vec![
+            import("git@github.com:org/added.git", "d"),
+            import("git@github.com:org/moved.git", "e"),
+            import("git@github.com:org/unchanged.git", "c"),
+        ]);
+
+        let changes: Vec<(String, Option<String>, Option<String>)> = current
+            .changes_since(&previous)
+            .into_iter()
+            .map(|c| (c.url.to_string(), c.old_commit, c.new_commit))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (
+                    String::from("git@github.com:org/added.git"),
+                    None,
+                    Some(String::from("d"))
+                ),
+                (
+                    String::from("git@github.com:org/moved.git"),
+                    Some(String::from("a")),
+                    Some(String::from("e"))
+                ),
+                (
+                    String::from("git@github.com:org/removed.git"),
+                    Some(String::from("b")),
+                    None
+                ),
+            ],
+            changes
+        );
+    }
+
     #[test]
     fn test_correctly_parses_legacy_lock() {
         let lock_contents = "--- \
@@ -252,6 +607,8 @@ mod tests {
                     "git@github.skyscannertools.net:cell-placement/cell-metadata-service.git",
                 )
                 .unwrap(),
+                transitive: false,
+                via: Vec::new(),
             }],
             min_protovend_version: Version::from_str("0.1.8").unwrap(),
             updated: NaiveDateTime::from_str("2019-11-20T15:02:12.330896").unwrap(),
@@ -283,6 +640,8 @@ mod tests {
                     "git@github.skyscannertools.net:cell-placement/cell-metadata-service.git",
                 )
                 .unwrap(),
+                transitive: false,
+                via: Vec::new(),
             }],
             min_protovend_version: Version::from_str("0.1.8").unwrap(),
             updated: NaiveDateTime::from_str("2019-11-20T15:02:12.330896").unwrap(),
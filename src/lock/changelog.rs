@@ -0,0 +1,223 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::outdated::short;
+use crate::git::{self, CommitInfo};
+use crate::git_url::GitUrl;
+use crate::summary::DependencyChange;
+use crate::{Result, PROTOS_DIRECTORY};
+use git2::Oid;
+use std::fmt::Write;
+
+/// Markdown describing the upstream commits behind each change, for pull request descriptions.
+pub(super) fn render(changes: &[DependencyChange]) -> Result<String> {
+    let mut markdown = String::from("## Protobuf dependency changes\n");
+
+    if changes.is_empty() {
+        markdown.push_str("\nNo dependencies changed.\n");
+    }
+
+    for change in changes {
+        let web_url = web_url(&change.url);
+        let history = match (&change.old_commit, &change.new_commit) {
+            (Some(old), Some(new)) => commits_touching_protos(change, old, new),
+            _ => History::NotMoved,
+        };
+        markdown.push_str(&render_change(change, &web_url, history));
+    }
+
+    Ok(markdown)
+}
+
+/// The upstream commits behind a change to a dependency.
+enum History {
+    /// The dependency was added or removed rather than moved to a new commit.
+    NotMoved,
+    /// The commits between the old and new commit could not be read.
+    Unavailable,
+    Commits(Vec<CommitInfo>),
+}
+
+fn commits_touching_protos(change: &DependencyChange, old: &str, new: &str) -> History {
+    let history = git::get_repo(&change.url, &change.branch, new).and_then(|repo| {
+        let proto_dir = PROTOS_DIRECTORY.join(change.url.sanitised_path());
+        git::commits_between(&repo, old, Oid::from_str(new)?, Some(&proto_dir))
+    });
+
+    match history {
+        Ok(commits) => History::Commits(commits),
+        Err(e) => {
+            log::warn!(
+                "Unable to list commits of {} between {} and {}: {}",
+                change.url,
+                old,
+                new,
+                e
+            );
+            History::Unavailable
+        }
+    }
+}
+
+/// Renders one dependency, listing the commits of its history where it was moved.
+fn render_change(change: &DependencyChange, web_url: &str, history: History) -> String {
+    let mut markdown = String::new();
+    let path = change.url.path();
+
+    match (&change.old_commit, &change.new_commit) {
+        (Some(old), Some(new)) => {
+            let _ = writeln!(
+                markdown,
+                "\n### [{}]({}) `{}` → `{}` ([compare]({}/compare/{}...{}))\n",
+                path,
+                web_url,
+                short(old),
+                short(new),
+                web_url,
+                old,
+                new
+            );
+            match history {
+                History::Commits(commits) if commits.is_empty() => {
+                    let _ = writeln!(
+                        markdown,
+                        "_No changes to `{}`._",
+                        PROTOS_DIRECTORY.join(change.url.sanitised_path()).display()
+                    );
+                }
+                History::Commits(commits) => {
+                    for commit in commits {
+                        let id = commit.id.to_string();
+                        let _ = writeln!(
+                            markdown,
+                            "- [`{}`]({}/commit/{}) {} ({})",
+                            short(&id),
+                            web_url,
+                            id,
+                            escape(&commit.summary),
+                            escape(&commit.author)
+                        );
+                    }
+                }
+                History::NotMoved | History::Unavailable => {
+                    let _ = writeln!(markdown, "_Commit history is unavailable._");
+                }
+            }
+        }
+        (None, Some(new)) => {
+            let _ = writeln!(
+                markdown,
+                "\n### [{}]({}) added at [`{}`]({}/commit/{})",
+                path,
+                web_url,
+                short(new),
+                web_url,
+                new
+            );
+        }
+        (Some(old), None) => {
+            let _ = writeln!(
+                markdown,
+                "\n### [{}]({}) removed, previously at `{}`",
+                path,
+                web_url,
+                short(old)
+            );
+        }
+        (None, None) => {}
+    }
+
+    markdown
+}
+
+/// Browsable https URL of a repo, dropping any user and port from the host.
+fn web_url(url: &GitUrl) -> String {
+    let host = url.host();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    format!("https://{}/{}", host, url.path())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace('`', "\\`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::str::FromStr;
+
+    fn change(old: Option<&str>, new: Option<&str>) -> DependencyChange {
+        DependencyChange {
+            url: GitUrl::from_str("git@github.com:org/repo.git").unwrap(),
+            branch: String::from("master"),
+            old_commit: old.map(String::from),
+            new_commit: new.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_web_url_is_derived_from_git_url() {
+        assert_eq!(
+            "https://github.com/org/repo",
+            web_url(&GitUrl::from_str("git@github.com:org/repo.git").unwrap())
+        );
+        assert_eq!(
+            "https://example.com/org/repo",
+            web_url(&GitUrl::from_str("ssh://git@example.com:22/org/repo.git").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_commits_are_listed_with_links() {
+        let old = "1111111111111111111111111111111111111111";
+        let new = "2222222222222222222222222222222222222222";
+        let commit = CommitInfo {
+            id: Oid::from_str(new).unwrap(),
+            summary: String::from("Add [optional] field"),
+            author: String::from("Jane Doe"),
+            time: Utc::now(),
+        };
+
+        let markdown = render_change(
+            &change(Some(old), Some(new)),
+            "https://github.com/org/repo",
+            History::Commits(vec![commit]),
+        );
+
+        assert_eq!(
+            "\n### [org/repo](https://github.com/org/repo) `1111111` → `2222222` \
+             ([compare](https://github.com/org/repo/compare/1111111111111111111111111111111111111111...2222222222222222222222222222222222222222))\n\
+             \n- [`2222222`](https://github.com/org/repo/commit/2222222222222222222222222222222222222222) \
+             Add \\[optional\\] field (Jane Doe)\n",
+            markdown
+        );
+    }
+
+    #[test]
+    fn test_unchanged_protos_are_noted() {
+        let markdown = render_change(
+            &change(Some("a"), Some("b")),
+            "https://github.com/org/repo",
+            History::Commits(Vec::new()),
+        );
+
+        assert!(markdown.ends_with("_No changes to `proto/org/repo`._\n"));
+    }
+}
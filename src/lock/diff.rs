@@ -0,0 +1,115 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::vendor::PROTOS_OUTPUT_DIRECTORY;
+use crate::summary::DependencyChange;
+use crate::{git, Result, PROTOS_DIRECTORY};
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Unified diff between a dependency's currently vendored protos and those at its new commit.
+pub(super) fn vendored_diff(change: &DependencyChange) -> Result<String> {
+    let sanitised_path = change.url.sanitised_path();
+    let mut files: BTreeMap<PathBuf, (Option<String>, Option<String>)> = BTreeMap::new();
+
+    let vendored_dir = PROTOS_OUTPUT_DIRECTORY.join(&sanitised_path);
+    if vendored_dir.exists() {
+        for entry in WalkDir::new(&vendored_dir) {
+            let entry = entry?;
+            if entry.metadata()?.is_file() && entry.path().extension() == Some(OsStr::new("proto"))
+            {
+                let relative = entry.path().strip_prefix(&vendored_dir)?.to_path_buf();
+                let contents = String::from_utf8_lossy(&fs::read(entry.path())?).into_owned();
+                files.entry(relative).or_default().0 = Some(contents);
+            }
+        }
+    }
+
+    if let Some(commit) = &change.new_commit {
+        let repo = git::get_repo(&change.url, &change.branch, commit)?;
+        let proto_dir = PROTOS_DIRECTORY.join(&sanitised_path);
+        for (relative, contents) in git::read_protos_at(&repo, commit, &proto_dir)? {
+            files.entry(relative).or_default().1 = Some(contents);
+        }
+    }
+
+    let diff = files
+        .iter()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(relative, (old, new))| {
+            unified_diff(&vendored_dir.join(relative), old.as_deref(), new.as_deref())
+        })
+        .collect();
+
+    Ok(diff)
+}
+
+/// Formats a diff in the same way as `git diff`, using /dev/null for added or deleted files.
+fn unified_diff(path: &Path, old: Option<&str>, new: Option<&str>) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let old_header = old.map_or_else(|| String::from("/dev/null"), |_| format!("a/{}", path));
+    let new_header = new.map_or_else(|| String::from("/dev/null"), |_| format!("b/{}", path));
+
+    TextDiff::from_lines(old.unwrap_or_default(), new.unwrap_or_default())
+        .unified_diff()
+        .context_radius(3)
+        .header(&old_header, &new_header)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_file_is_diffed() {
+        let diff = unified_diff(
+            Path::new("vendor/proto/org/repo/a.proto"),
+            Some("syntax = \"proto3\";\nmessage A {\n  string b = 1;\n}\n"),
+            Some("syntax = \"proto3\";\nmessage A {\n  string b = 1;\n  int32 c = 2;\n}\n"),
+        );
+
+        assert_eq!(
+            "--- a/vendor/proto/org/repo/a.proto\
+             \n+++ b/vendor/proto/org/repo/a.proto\
+             \n@@ -1,4 +1,5 @@\
+             \n syntax = \"proto3\";\
+             \n message A {\
+             \n   string b = 1;\
+             \n+  int32 c = 2;\
+             \n }\n",
+            diff
+        );
+    }
+
+    #[test]
+    fn test_added_file_is_diffed_against_dev_null() {
+        let diff = unified_diff(
+            Path::new("vendor/proto/org/repo/a.proto"),
+            None,
+            Some("a\n"),
+        );
+
+        assert_eq!(
+            "--- /dev/null\n+++ b/vendor/proto/org/repo/a.proto\n@@ -0,0 +1 @@\n+a\n",
+            diff
+        );
+    }
+}
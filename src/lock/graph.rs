@@ -0,0 +1,212 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::inspect::{own_protos, provider, vendored_files};
+use super::vendor::PROTOS_OUTPUT_DIRECTORY;
+use super::ProtovendLock;
+use crate::config::ProtovendConfig;
+use crate::{proto, Result};
+use failure::format_err;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(format_err!(
+                "Unknown graph format {}; expected dot or json",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum NodeKind {
+    Project,
+    Repo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EdgeKind {
+    /// The project lists the repo in its protovend metadata file.
+    Declared,
+    /// The repo was vendored because a dependency declares it in its own metadata file.
+    Transitive,
+    /// Protos of one node import protos vendored from the repo.
+    Imports,
+}
+
+/// Projects and the repos they vendor, joined by why one depends on the other.
+#[derive(Debug, Default)]
+pub struct Graph {
+    nodes: BTreeMap<String, NodeKind>,
+    edges: BTreeSet<(String, String, EdgeKind)>,
+}
+
+impl Graph {
+    /// Adds the dependencies of the project at `root`, shown as `name`.
+    pub fn add_project(
+        &mut self,
+        name: &str,
+        root: &Path,
+        config: &ProtovendConfig,
+        lock: &ProtovendLock,
+    ) -> Result<()> {
+        self.nodes.insert(name.to_owned(), NodeKind::Project);
+
+        for dep in config.vendor.iter() {
+            self.add_edge(name, dep.url.as_str(), EdgeKind::Declared);
+        }
+        let output_dir = root.join(PROTOS_OUTPUT_DIRECTORY.as_path());
+        for import in lock.imports.iter() {
+            if let Some(parent) = import.via.last() {
+                self.add_edge(parent.as_str(), import.url.as_str(), EdgeKind::Transitive);
+            }
+
+            for file in vendored_files(root, import) {
+                let contents = fs::read_to_string(output_dir.join(file))?;
+                for statement in proto::imports(&contents) {
+                    if let Some(provider) = provider(&statement, &lock.imports) {
+                        if provider.url != import.url {
+                            self.add_edge(
+                                import.url.as_str(),
+                                provider.url.as_str(),
+                                EdgeKind::Imports,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        for (_, statements) in own_protos(root)? {
+            for statement in statements {
+                if let Some(provider) = provider(&statement, &lock.imports) {
+                    self.add_edge(name, provider.url.as_str(), EdgeKind::Imports);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_edge(&mut self, from: &str, to: &str, kind: EdgeKind) {
+        self.nodes.entry(from.to_owned()).or_insert(NodeKind::Repo);
+        self.nodes.entry(to.to_owned()).or_insert(NodeKind::Repo);
+        self.edges.insert((from.to_owned(), to.to_owned(), kind));
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => self.to_json(),
+        }
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph protovend {\n");
+        for (id, kind) in self.nodes.iter() {
+            let shape = match kind {
+                NodeKind::Project => "box",
+                NodeKind::Repo => "ellipse",
+            };
+            dot.push_str(&format!("  {} [shape={}];\n", quote(id), shape));
+        }
+        for (from, to, kind) in self.edges.iter() {
+            let style = match kind {
+                EdgeKind::Declared => "solid",
+                EdgeKind::Transitive => "dashed",
+                EdgeKind::Imports => "dotted",
+            };
+            dot.push_str(&format!(
+                "  {} -> {} [style={}];\n",
+                quote(from),
+                quote(to),
+                style
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_json(&self) -> String {
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|(id, kind)| json!({ "id": id, "kind": kind }))
+            .collect();
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|(from, to, kind)| json!({ "from": from, "to": to, "kind": kind }))
+            .collect();
+
+        json!({ "nodes": nodes, "edges": edges }).to_string()
+    }
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_output() {
+        let mut graph = Graph::default();
+        graph.nodes.insert("consumer".to_owned(), NodeKind::Project);
+        graph.add_edge(
+            "consumer",
+            "git@github.com:org/producer.git",
+            EdgeKind::Declared,
+        );
+        graph.add_edge(
+            "git@github.com:org/producer.git",
+            "git@github.com:org/types.git",
+            EdgeKind::Transitive,
+        );
+
+        assert_eq!(
+            "digraph protovend {
+  \"consumer\" [shape=box];
+  \"git@github.com:org/producer.git\" [shape=ellipse];
+  \"git@github.com:org/types.git\" [shape=ellipse];
+  \"consumer\" -> \"git@github.com:org/producer.git\" [style=solid];
+  \"git@github.com:org/producer.git\" -> \"git@github.com:org/types.git\" [style=dashed];
+}
+",
+            graph.render(GraphFormat::Dot)
+        );
+    }
+}
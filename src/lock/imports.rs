@@ -0,0 +1,140 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::vendor::{Provider, VendorPlan, PROTOS_OUTPUT_DIRECTORY};
+use crate::{proto, PROTOS_DIRECTORY};
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub(super) struct UnresolvedImport {
+    file: PathBuf,
+    import: String,
+    provider: Provider,
+}
+
+impl UnresolvedImport {
+    /// Vendored files live under <org>/<repo>, so the first two segments of the import
+    /// are our best guess at the repo that should be providing it.
+    fn suggested_repo(&self) -> Option<String> {
+        let segments: Vec<&str> = self.import.split('/').collect();
+        if segments.len() > 2 {
+            Some(format!(
+                "{}:{}/{}",
+                self.provider.url.host(),
+                segments[0],
+                segments[1]
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for UnresolvedImport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} from {} imports \"{}\" which cannot be found",
+            PROTOS_OUTPUT_DIRECTORY.join(&self.file).display(),
+            self.provider,
+            self.import
+        )?;
+        if let Some(repo) = self.suggested_repo() {
+            write!(f, "; it is probably provided by a repo matching {}", repo)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks every import statement in the vendored files against the vendored tree, the
+/// project's own proto directory and the well known types shipped with protoc.
+pub(super) fn report_unresolved(plan: &VendorPlan) {
+    let unresolved = find_unresolved(plan, |import| {
+        PROTOS_OUTPUT_DIRECTORY.join(import).is_file() || PROTOS_DIRECTORY.join(import).is_file()
+    });
+
+    for result in unresolved.iter() {
+        log::warn!("{}", result);
+    }
+
+    if !unresolved.is_empty() {
+        log::warn!(
+            "{} import(s) in vendored files could not be resolved",
+            unresolved.len()
+        );
+    }
+}
+
+fn find_unresolved<F>(plan: &VendorPlan, exists: F) -> Vec<UnresolvedImport>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut unresolved = Vec::new();
+
+    for (file, provider, contents) in plan.files() {
+        for import in proto::imports(&String::from_utf8_lossy(contents)) {
+            if !proto::is_well_known(&import) && !exists(Path::new(&import)) {
+                unresolved.push(UnresolvedImport {
+                    file: file.to_path_buf(),
+                    import,
+                    provider: provider.clone(),
+                });
+            }
+        }
+    }
+
+    unresolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_url::GitUrl;
+    use std::str::FromStr;
+
+    fn provider() -> Provider {
+        Provider {
+            url: GitUrl::from_str("git@github.com:org/consumer.git").unwrap(),
+            branch: String::from("master"),
+        }
+    }
+
+    #[test]
+    fn test_unresolved_imports_are_reported_with_suggestion() {
+        let contents = "import \"google/protobuf/empty.proto\";\
+                        \nimport \"org/consumer/local.proto\";\
+                        \nimport \"org/producer/missing.proto\";";
+
+        let mut plan = VendorPlan::default();
+        plan.insert(
+            PathBuf::from("org/consumer/service.proto"),
+            provider(),
+            contents.as_bytes().to_vec(),
+        );
+
+        let unresolved = find_unresolved(&plan, |import| {
+            import == Path::new("org/consumer/local.proto")
+        });
+
+        assert_eq!(1, unresolved.len());
+        assert_eq!("org/producer/missing.proto", unresolved[0].import);
+        assert_eq!(
+            Some(String::from("github.com:org/producer")),
+            unresolved[0].suggested_repo()
+        );
+    }
+}
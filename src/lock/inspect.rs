@@ -0,0 +1,392 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::outdated::short;
+use super::vendor::PROTOS_OUTPUT_DIRECTORY;
+use super::Import;
+use crate::config::ProtovendConfig;
+use crate::git_url::GitUrl;
+use crate::proto::{Message, ProtoFile};
+use crate::summary::{ImportedFile, ListedDependency, TracedProto, TreeDependency, VendoredFile};
+use crate::{proto, Result, PROTOS_DIRECTORY};
+use chrono::NaiveDateTime;
+use failure::format_err;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Logs one line per declared dependency, and per import pulled in transitively, with its
+/// locked commit, the number of files vendored for it and whether the lock matches the config.
+pub(super) fn list(
+    imports: &[Import],
+    config: &ProtovendConfig,
+    updated: NaiveDateTime,
+) -> Vec<ListedDependency> {
+    if config.vendor.is_empty() && imports.is_empty() {
+        log::info!("No dependencies declared");
+        return Vec::new();
+    }
+
+    log::info!("Lock updated {}", updated.format("%Y-%m-%d %H:%M:%S"));
+
+    let mut rows = Vec::new();
+    for dep in config.vendor.iter() {
+        let locked = imports
+            .iter()
+            .find(|import| import.via.is_empty() && import.url == dep.url);
+        let status = match locked {
+            Some(import) if import == dep => "in sync",
+            Some(_) => "locked with different settings",
+            None => "not locked",
+        };
+        rows.push(row(&dep.url, &dep.branch, locked, status));
+    }
+    for import in imports.iter() {
+        let status = if !import.via.is_empty() {
+            format!("via {}", import.via.last().unwrap())
+        } else if config.vendor.iter().any(|dep| dep.url == import.url) {
+            continue;
+        } else {
+            "locked but not a dependency".to_owned()
+        };
+        rows.push(row(&import.url, &import.branch, Some(import), &status));
+    }
+
+    let url_width = rows.iter().map(|r| r.url.as_str().len()).max().unwrap_or(0);
+    let branch_width = rows.iter().map(|r| r.branch.len()).max().unwrap_or(0);
+    for r in rows.iter() {
+        log::info!(
+            "{:url_width$}  {:branch_width$}  {}  {:>3} file(s)  {}",
+            r.url.as_str(),
+            r.branch,
+            r.commit.as_deref().map_or("-------", short),
+            r.files
+                .map_or_else(|| "-".to_owned(), |files| files.to_string()),
+            r.status,
+            url_width = url_width,
+            branch_width = branch_width,
+        );
+    }
+    rows
+}
+
+fn row(url: &GitUrl, branch: &str, import: Option<&Import>, status: &str) -> ListedDependency {
+    ListedDependency {
+        url: url.clone(),
+        branch: branch.to_owned(),
+        commit: import.map(|import| import.commit.clone()),
+        files: import.map(|import| vendored_files(Path::new(""), import).len()),
+        status: status.to_owned(),
+    }
+}
+
+/// Logs the files vendored for each import, with each import statement in them and the
+/// dependency that provides the imported file.
+pub(super) fn tree(imports: &[Import]) -> Result<Vec<TreeDependency>> {
+    if imports.is_empty() {
+        log::info!("No dependencies locked");
+        return Ok(Vec::new());
+    }
+
+    let mut tree = Vec::new();
+    for import in imports.iter() {
+        log::info!(
+            "{} ({} @ {})",
+            import.url,
+            import.branch,
+            short(&import.commit)
+        );
+
+        let paths = vendored_files(Path::new(""), import);
+        if paths.is_empty() {
+            log::info!("└── (nothing vendored, run protovend install)");
+        }
+        let mut files = Vec::new();
+        for (i, path) in paths.iter().enumerate() {
+            let last_file = i + 1 == paths.len();
+            let (branch, indent) = if last_file {
+                ("└──", "    ")
+            } else {
+                ("├──", "│   ")
+            };
+            log::info!("{} {}", branch, path.display());
+
+            let contents = fs::read_to_string(PROTOS_OUTPUT_DIRECTORY.join(path))?;
+            let statements = proto::imports(&contents);
+            let count = statements.len();
+            let mut imported = Vec::new();
+            for (j, statement) in statements.into_iter().enumerate() {
+                let twig = if j + 1 == count {
+                    "└──"
+                } else {
+                    "├──"
+                };
+                let provider = provider_of(&statement, imports);
+                log::info!("{}{} {} ({})", indent, twig, statement, provider);
+                imported.push(ImportedFile {
+                    file: statement,
+                    provider,
+                });
+            }
+            files.push(VendoredFile {
+                path: path.clone(),
+                imports: imported,
+            });
+        }
+        tree.push(TreeDependency {
+            url: import.url.clone(),
+            branch: import.branch.clone(),
+            commit: import.commit.clone(),
+            files,
+        });
+    }
+
+    Ok(tree)
+}
+
+/// Logs the import each vendored file matching `query` came from, and the project's protos
+/// importing it. The query is a vendored file, a proto package or a fully qualified name.
+pub(super) fn why(imports: &[Import], query: &str) -> Result<Vec<TracedProto>> {
+    let matches = find_vendored(imports, query)?;
+    if matches.is_empty() {
+        return Err(format_err!("No vendored proto matches {}", query));
+    }
+
+    let own_protos = own_protos(Path::new(""))?;
+    let mut traced = Vec::new();
+    for (file, matched) in matches {
+        let import = imports
+            .iter()
+            .find(|import| file.starts_with(import.url.sanitised_path()))
+            .ok_or_else(|| format_err!("{} is not vendored from a locked repo", file.display()))?;
+
+        log::info!(
+            "{}{} is vendored from {} ({} branch) at {}, source {}",
+            PROTOS_OUTPUT_DIRECTORY.join(&file).display(),
+            matched
+                .as_ref()
+                .map_or_else(String::new, |matched| format!(" ({})", matched)),
+            import.url,
+            import.branch,
+            import.commit,
+            PROTOS_DIRECTORY.join(&file).display()
+        );
+        if !import.via.is_empty() {
+            let chain: Vec<String> = import.via.iter().map(|url| url.to_string()).collect();
+            log::info!("  pulled in transitively via {}", chain.join(" -> "));
+        }
+
+        let importers: Vec<PathBuf> = own_protos
+            .iter()
+            .filter(|(_, imports)| imports.iter().any(|i| Path::new(i) == file))
+            .map(|(path, _)| path.clone())
+            .collect();
+        if importers.is_empty() {
+            log::info!("  not imported by any of this project's protos");
+        }
+        for importer in importers.iter() {
+            log::info!("  imported by {}", importer.display());
+        }
+
+        traced.push(TracedProto {
+            file: PROTOS_OUTPUT_DIRECTORY.join(&file),
+            matched,
+            url: import.url.clone(),
+            branch: import.branch.clone(),
+            commit: import.commit.clone(),
+            via: import.via.clone(),
+            imported_by: importers,
+        });
+    }
+
+    Ok(traced)
+}
+
+/// Vendored files matching a query, relative to the output directory, with the package or
+/// name they matched on.
+fn find_vendored(imports: &[Import], query: &str) -> Result<Vec<(PathBuf, Option<String>)>> {
+    if query.ends_with(".proto") {
+        let path = Path::new(query);
+        let file = path
+            .strip_prefix(PROTOS_OUTPUT_DIRECTORY.as_path())
+            .unwrap_or(path);
+        return Ok(if PROTOS_OUTPUT_DIRECTORY.join(file).is_file() {
+            vec![(file.to_path_buf(), None)]
+        } else {
+            Vec::new()
+        });
+    }
+
+    let name = query.trim_start_matches('.');
+    let mut matches = Vec::new();
+    for file in imports
+        .iter()
+        .flat_map(|import| vendored_files(Path::new(""), import))
+    {
+        let parsed = match proto::parse_file(&PROTOS_OUTPUT_DIRECTORY.join(&file))? {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                log::debug!("Skipping {}: {}", file.display(), e);
+                continue;
+            }
+        };
+        let package = parsed.package.as_ref().map(|p| p.name.as_str());
+
+        if package == Some(name) {
+            matches.push((file, Some(format!("package {}", name))));
+        } else if defines(&parsed, name) {
+            matches.push((file, Some(format!("defines {}", name))));
+        }
+    }
+
+    Ok(matches)
+}
+
+/// Whether a file declares a message, enum or service with the given fully qualified name.
+fn defines(file: &ProtoFile, name: &str) -> bool {
+    let local = match &file.package {
+        Some(package) => match name.strip_prefix(&format!("{}.", package.name)) {
+            Some(local) => local,
+            None => return false,
+        },
+        None => name,
+    };
+
+    file.enums.iter().any(|e| e.name == local)
+        || file.services.iter().any(|s| s.name == local)
+        || file.messages.iter().any(|m| message_defines(m, local))
+}
+
+fn message_defines(message: &Message, name: &str) -> bool {
+    if message.name == name {
+        return true;
+    }
+    match name.strip_prefix(&format!("{}.", message.name)) {
+        Some(nested) => {
+            message.enums.iter().any(|e| e.name == nested)
+                || message.messages.iter().any(|m| message_defines(m, nested))
+        }
+        None => false,
+    }
+}
+
+/// The own proto files of the project at `root` with the imports each declares.
+pub(super) fn own_protos(root: &Path) -> Result<Vec<(PathBuf, Vec<String>)>> {
+    let mut protos = Vec::new();
+    for entry in WalkDir::new(root.join(PROTOS_DIRECTORY.as_path())) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if entry.file_type().is_file() && entry.path().extension() == Some("proto".as_ref()) {
+            let contents = fs::read_to_string(entry.path())?;
+            protos.push((entry.into_path(), proto::imports(&contents)));
+        }
+    }
+    protos.sort();
+    Ok(protos)
+}
+
+/// Paths, relative to the output directory, of the files vendored for an import in the
+/// project at `root`.
+pub(super) fn vendored_files(root: &Path, import: &Import) -> Vec<PathBuf> {
+    let output_dir = root.join(PROTOS_OUTPUT_DIRECTORY.as_path());
+    let mut files: Vec<PathBuf> = WalkDir::new(output_dir.join(import.url.sanitised_path()))
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            entry
+                .path()
+                .strip_prefix(&output_dir)
+                .ok()
+                .map(Path::to_path_buf)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// The locked import whose vendored directory holds the imported file.
+pub(super) fn provider<'a>(statement: &str, imports: &'a [Import]) -> Option<&'a Import> {
+    imports
+        .iter()
+        .find(|import| Path::new(statement).starts_with(import.url.sanitised_path()))
+}
+
+/// Describes where an imported file comes from.
+fn provider_of(statement: &str, imports: &[Import]) -> String {
+    if let Some(import) = provider(statement, imports) {
+        import.url.to_string()
+    } else if proto::is_well_known(statement) {
+        "well known type".to_owned()
+    } else if PROTOS_DIRECTORY.join(statement).is_file() {
+        "this project".to_owned()
+    } else {
+        "not found".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn import(url: &str) -> Import {
+        Import {
+            branch: "master".to_owned(),
+            commit: "4d1e0f2".to_owned(),
+            url: GitUrl::from_str(url).unwrap(),
+            transitive: false,
+            via: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_provider_of_import_statements() {
+        let imports = vec![
+            import("git@github.com:org/first.git"),
+            import("git@github.com:org/second.git"),
+        ];
+
+        assert_eq!(
+            "git@github.com:org/second.git",
+            provider_of("org/second/types.proto", &imports)
+        );
+        assert_eq!(
+            "well known type",
+            provider_of("google/protobuf/timestamp.proto", &imports)
+        );
+        assert_eq!("not found", provider_of("org/third/a.proto", &imports));
+    }
+
+    #[test]
+    fn test_defines_fully_qualified_names() {
+        let file = proto::parse(
+            "syntax = \"proto3\";
+            package org.types.v1;
+            message Money { enum Currency { CURRENCY_UNSPECIFIED = 0; } }
+            service Rates {}",
+        )
+        .unwrap();
+
+        assert!(defines(&file, "org.types.v1.Money"));
+        assert!(defines(&file, "org.types.v1.Money.Currency"));
+        assert!(defines(&file, "org.types.v1.Rates"));
+        assert!(!defines(&file, "org.types.v1.Currency"));
+        assert!(!defines(&file, "other.Money"));
+    }
+}
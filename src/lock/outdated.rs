@@ -0,0 +1,108 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::Import;
+use crate::git;
+use crate::summary::DependencyChange;
+use crate::Result;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeSet;
+
+/// Compares each direct import with the newest revision of its branch, logging how far behind
+/// it is and returning those with newer commits.
+pub(super) fn find(imports: &[Import]) -> Result<Vec<DependencyChange>> {
+    let mut outdated = Vec::new();
+
+    for import in imports.iter().filter(|import| import.via.is_empty()) {
+        let repo = git::fetch_repo(&import.url, &import.branch)?;
+        let (latest_name, latest) = git::latest_revision(&repo, &import.branch)?;
+
+        if latest.to_string() == import.commit {
+            log::info!("{} ({}) is up to date", import.url, import.branch);
+            continue;
+        }
+
+        let locked = git::commit_info(&repo, &import.commit)?;
+        let commits = git::commits_between(&repo, &import.commit, latest, None)?;
+        let authors: BTreeSet<&str> = commits.iter().map(|c| c.author.as_str()).collect();
+
+        log::info!(
+            "{} ({}) is {} commit(s) behind {}: locked {} from {}, latest {}",
+            import.url,
+            import.branch,
+            commits.len(),
+            latest_name,
+            short(&import.commit),
+            describe_age(locked.time, Utc::now()),
+            short(&latest.to_string()),
+        );
+        if !authors.is_empty() {
+            log::info!(
+                "  changes by {}",
+                authors.into_iter().collect::<Vec<_>>().join(", ")
+            );
+        }
+
+        outdated.push(DependencyChange {
+            url: import.url.clone(),
+            branch: import.branch.clone(),
+            old_commit: Some(import.commit.clone()),
+            new_commit: Some(latest.to_string()),
+        });
+    }
+
+    if outdated.is_empty() {
+        log::info!("All dependencies are up to date");
+    } else {
+        log::warn!(
+            "{} dependencies are outdated; run protovend update to update them",
+            outdated.len()
+        );
+    }
+
+    Ok(outdated)
+}
+
+pub(super) fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
+
+pub(super) fn describe_age(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let age = now.signed_duration_since(time);
+    match age.num_days() {
+        days if days > 0 => format!("{} day(s) ago", days),
+        _ if age.num_hours() > 0 => format!("{} hour(s) ago", age.num_hours()),
+        _ => String::from("less than an hour ago"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_age_is_described_in_largest_unit() {
+        let now = Utc::now();
+
+        assert_eq!("3 day(s) ago", describe_age(now - Duration::hours(80), now));
+        assert_eq!("5 hour(s) ago", describe_age(now - Duration::hours(5), now));
+        assert_eq!(
+            "less than an hour ago",
+            describe_age(now - Duration::minutes(5), now)
+        );
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::outdated::{describe_age, short};
+use super::{load_lockfile, PROTOVEND_LOCK};
+use crate::config::{self, PROTOVEND_YAML};
+use crate::git;
+use crate::git_url::GitUrl;
+use crate::summary::Consumer;
+use crate::Result;
+use chrono::{Local, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use walkdir::{DirEntry, WalkDir};
+
+/// Finds the protovend projects under `dirs` and every repo they vendor, optionally only
+/// those vendoring `producer`. With `fetch`, counts the commits each lock is behind.
+pub fn scan(dirs: &[PathBuf], producer: Option<&GitUrl>, fetch: bool) -> Result<Vec<Consumer>> {
+    let mut consumers = Vec::new();
+
+    for project in dirs.iter().flat_map(|dir| find_projects(dir)) {
+        let config = match config::load_config(&project.join(PROTOVEND_YAML.as_path())) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!("Skipping {}: {}", project.display(), e);
+                continue;
+            }
+        };
+        let lock_file = project.join(PROTOVEND_LOCK.as_path());
+        if !lock_file.is_file() {
+            log::warn!(
+                "Skipping {}: no {} found",
+                project.display(),
+                PROTOVEND_LOCK.display()
+            );
+            continue;
+        }
+        let lock = match load_lockfile(&lock_file) {
+            Ok(lock) => lock,
+            Err(e) => {
+                log::warn!("Skipping {}: {}", project.display(), e);
+                continue;
+            }
+        };
+
+        let name = match &config.project_url {
+            Some(url) => url.to_string(),
+            None => project.display().to_string(),
+        };
+        for import in lock.imports.iter() {
+            if producer.is_some_and(|producer| !producer.is_same_repo(&import.url)) {
+                continue;
+            }
+            consumers.push(Consumer {
+                producer: import.url.clone(),
+                project: name.clone(),
+                path: project.clone(),
+                branch: import.branch.clone(),
+                commit: import.commit.clone(),
+                via: import.via.clone(),
+                locked: lock.updated,
+                commits_behind: None,
+            });
+        }
+    }
+
+    if fetch {
+        count_commits_behind(&mut consumers);
+    }
+    report(&consumers);
+
+    Ok(consumers)
+}
+
+/// Directories holding a protovend metadata file, skipping hidden and vendored directories.
+fn find_projects(dir: &Path) -> Vec<PathBuf> {
+    let is_skipped = |entry: &DirEntry| {
+        let name = entry.file_name().to_string_lossy();
+        entry.depth() > 0
+            && entry.file_type().is_dir()
+            && (name.starts_with('.') || name == "vendor" || name == "target")
+    };
+
+    let mut projects: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|entry| !is_skipped(entry))
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_type().is_file() && entry.file_name() == PROTOVEND_YAML.as_os_str()
+        })
+        .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+        .collect();
+    projects.sort();
+    projects
+}
+
+/// Fetches each producer once, comparing every consumer's locked commit with the newest on its
+/// branch. Each clone is released before the next is fetched, as it is locked while held.
+fn count_commits_behind(consumers: &mut [Consumer]) {
+    let mut by_producer: BTreeMap<String, Vec<&mut Consumer>> = BTreeMap::new();
+    for consumer in consumers.iter_mut() {
+        by_producer
+            .entry(consumer.producer.to_string())
+            .or_default()
+            .push(consumer);
+    }
+
+    for (_, consumers) in by_producer {
+        let producer = consumers[0].producer.clone();
+        let repo = match git::fetch_repo(&producer, &consumers[0].branch) {
+            Ok(repo) => repo,
+            Err(e) => {
+                log::warn!("Cannot fetch {}: {}", producer, e);
+                continue;
+            }
+        };
+
+        for consumer in consumers {
+            let behind = git::latest_revision(&repo, &consumer.branch).and_then(|(_, latest)| {
+                git::commits_between(&repo, &consumer.commit, latest, None)
+            });
+            match behind {
+                Ok(commits) => consumer.commits_behind = Some(commits.len()),
+                Err(e) => log::warn!(
+                    "Cannot compare {} with the {} branch of {}: {}",
+                    consumer.commit,
+                    consumer.branch,
+                    producer,
+                    e
+                ),
+            }
+        }
+    }
+}
+
+fn report(consumers: &[Consumer]) {
+    if consumers.is_empty() {
+        log::info!("No vendored dependencies found");
+        return;
+    }
+
+    let mut by_producer: BTreeMap<String, Vec<&Consumer>> = BTreeMap::new();
+    for consumer in consumers.iter() {
+        by_producer
+            .entry(consumer.producer.to_string())
+            .or_default()
+            .push(consumer);
+    }
+
+    let now = Utc::now();
+    for (producer, consumers) in by_producer {
+        log::info!("{} is vendored by {} project(s)", producer, consumers.len());
+        for consumer in consumers {
+            let mut line = format!(
+                "  {}: {} at {}",
+                consumer.project,
+                consumer.branch,
+                short(&consumer.commit)
+            );
+            if let Some(locked) = Local.from_local_datetime(&consumer.locked).earliest() {
+                line.push_str(&format!(
+                    ", locked {}",
+                    describe_age(locked.with_timezone(&Utc), now)
+                ));
+            }
+            if let Some(behind) = consumer.commits_behind {
+                line.push_str(&format!(", {} commit(s) behind", behind));
+            }
+            if let Some(parent) = consumer.via.last() {
+                line.push_str(&format!(", via {}", parent));
+            }
+            log::info!("{}", line);
+        }
+    }
+}
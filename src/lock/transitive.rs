@@ -0,0 +1,416 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::{Import, PROTOVEND_LOCK};
+use crate::config::{self, PROTOVEND_YAML};
+use crate::git;
+use crate::git_url::GitUrl;
+use crate::Result;
+use failure::format_err;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Expands every import marked as transitive with the dependencies declared in the producer's
+/// own .protovend.yml, flattening the resulting graph into a single list of imports.
+///
+/// Imports that are unchanged from the existing lock reuse the transitive imports recorded
+/// there, so only new or moved dependencies need their producer metadata fetched.
+pub(super) fn resolve<F>(
+    direct: Vec<Import>,
+    locked: &[Import],
+    mut producer_imports: F,
+) -> Result<Vec<Import>>
+where
+    F: FnMut(&Import) -> Result<Vec<Import>>,
+{
+    // Each producer's metadata is fetched at most once, however many passes need it
+    let mut fetched: Vec<(Import, Vec<Import>)> = Vec::new();
+    let mut producer_imports = |parent: &Import| -> Result<Vec<Import>> {
+        if let Some((_, children)) = fetched.iter().find(|(p, _)| same_commit(p, parent)) {
+            return Ok(children.clone());
+        }
+        let children = producer_imports(parent)?;
+        fetched.push((parent.clone(), children.clone()));
+        Ok(children)
+    };
+
+    let resolved = expand(direct.clone(), locked, &mut producer_imports)?;
+    let dropped: Vec<&GitUrl> = locked
+        .iter()
+        .filter(|old| !resolved.iter().any(|new| new.url == old.url))
+        .map(|old| &old.url)
+        .collect();
+    if dropped.is_empty() {
+        return Ok(resolved);
+    }
+
+    // The lock records a dependency reached by several paths under only one of them, so the
+    // children reused for an unchanged import can miss it when that path goes away. Check
+    // whether a producer whose children were reused still declares a dropped dependency.
+    let mut reachable = false;
+    for reused in resolved
+        .iter()
+        .filter(|import| import.transitive && locked.contains(import))
+    {
+        if producer_imports(reused)?
+            .iter()
+            .any(|child| dropped.contains(&&child.url))
+        {
+            reachable = true;
+            break;
+        }
+    }
+    if !reachable {
+        return Ok(resolved);
+    }
+
+    // Fetch the producer metadata again, keeping the locked commits of unchanged imports'
+    // children.
+    log::debug!(
+        "Dropped dependencies are still reachable; resolving transitive dependencies again"
+    );
+    expand(direct, &[], &mut |parent: &Import| {
+        let mut children = producer_imports(parent)?;
+        if locked.iter().any(|old| same_commit(old, parent)) {
+            for child in children.iter_mut() {
+                if let Some(old) = locked
+                    .iter()
+                    .find(|old| old.url == child.url && old.branch == child.branch)
+                {
+                    child.commit = old.commit.clone();
+                }
+            }
+        }
+        Ok(children)
+    })
+}
+
+fn same_commit(a: &Import, b: &Import) -> bool {
+    a.url == b.url && a.branch == b.branch && a.commit == b.commit && a.transitive == b.transitive
+}
+
+fn expand<F>(
+    direct: Vec<Import>,
+    locked: &[Import],
+    producer_imports: &mut F,
+) -> Result<Vec<Import>>
+where
+    F: FnMut(&Import) -> Result<Vec<Import>>,
+{
+    let mut resolved = Vec::new();
+    let mut pending: VecDeque<Import> = direct.into_iter().collect();
+
+    while let Some(import) = pending.pop_front() {
+        if import.transitive {
+            let mut path = import.via.clone();
+            path.push(import.url.clone());
+
+            let children = if locked.contains(&import) {
+                locked
+                    .iter()
+                    .filter(|child| child.via == path)
+                    .cloned()
+                    .collect()
+            } else {
+                let mut children = producer_imports(&import)?;
+                for child in children.iter_mut() {
+                    child.via = path.clone();
+                }
+                children
+            };
+
+            for child in children {
+                if path.contains(&child.url) {
+                    return Err(format_err!(
+                        "Dependency cycle detected: {}",
+                        describe_path(&child)
+                    ));
+                }
+                pending.push_back(child);
+            }
+        }
+
+        resolved.push(import);
+    }
+
+    deduplicate(resolved)
+}
+
+/// Collapses imports of the same repository reached through different paths, failing if
+/// they disagree on which commit should be vendored.
+fn deduplicate(imports: Vec<Import>) -> Result<Vec<Import>> {
+    let mut by_url: BTreeMap<GitUrl, Vec<Import>> = BTreeMap::new();
+    for import in imports {
+        by_url.entry(import.url.clone()).or_default().push(import);
+    }
+
+    let mut deduplicated = Vec::new();
+    let mut conflicts = 0;
+
+    for (url, mut imports) in by_url {
+        if imports.iter().all(|i| i.commit == imports[0].commit) {
+            deduplicated.push(imports.swap_remove(0));
+        } else {
+            conflicts += 1;
+            log::error!("{} is required at conflicting commits:", url);
+            for import in imports.iter() {
+                log::error!("  - {} via {}", import.commit, describe_path(import));
+            }
+        }
+    }
+
+    if conflicts == 0 {
+        Ok(deduplicated)
+    } else {
+        Err(format_err!(
+            "{} dependencies are required at conflicting commits",
+            conflicts
+        ))
+    }
+}
+
+fn describe_path(import: &Import) -> String {
+    if import.via.is_empty() {
+        format!("{} (direct dependency)", import.url)
+    } else {
+        let mut path: Vec<String> = import.via.iter().map(|url| url.to_string()).collect();
+        path.push(import.url.to_string());
+        path.join(" -> ")
+    }
+}
+
+/// Reads the dependencies a producer declares at the locked commit, pinning each to the commit
+/// in the producer's own lock where possible and to its branch head otherwise.
+pub(super) fn producer_imports(parent: &Import) -> Result<Vec<Import>> {
+    log::info!(
+        "Resolving transitive dependencies of {} at {}",
+        parent.url,
+        parent.commit
+    );
+    let (producer_config, producer_lock) = {
+        let repo = git::get_repo(&parent.url, &parent.branch, &parent.commit)?;
+        let clone_location = repo.workdir().unwrap(); //Can unwrap safely as repository is not bare
+
+        let config_file = clone_location.join(PROTOVEND_YAML.as_path());
+        if !config_file.is_file() {
+            log::debug!("{} does not declare any dependencies", parent.url);
+            return Ok(Vec::new());
+        }
+
+        (
+            config::load_config(&config_file)?,
+            super::load_lockfile(&clone_location.join(PROTOVEND_LOCK.as_path()))?,
+        )
+    };
+    // The producer's clone is unlocked by now, so fetching a child that shares it (or whose
+    // producer is fetching this one) cannot wait on a lock held here.
+
+    let pinned: Vec<Option<String>> = producer_config
+        .vendor
+        .iter()
+        .map(|dep| {
+            producer_lock
+                .imports
+                .iter()
+                .find(|i| i.via.is_empty() && i.url == dep.url && i.branch == dep.branch)
+                .map(|i| i.commit.clone())
+        })
+        .collect();
+
+    producer_config
+        .vendor
+        .into_iter()
+        .zip(pinned)
+        .map(|(dep, commit)| {
+            let commit = match commit {
+                Some(commit) => commit,
+                None => git::get_latest_commit_sha(&dep.url, &dep.branch)?.to_string(),
+            };
+            Ok(Import {
+                branch: dep.branch,
+                commit,
+                url: dep.url,
+                transitive: dep.transitive,
+                via: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    fn import(url: &str, commit: &str, transitive: bool) -> Import {
+        Import {
+            branch: String::from("master"),
+            commit: String::from(commit),
+            url: GitUrl::from_str(url).unwrap(),
+            transitive,
+            via: Vec::new(),
+        }
+    }
+
+    fn fake_producers(
+        graph: HashMap<&'static str, Vec<Import>>,
+    ) -> impl FnMut(&Import) -> Result<Vec<Import>> {
+        move |parent| Ok(graph.get(parent.url.as_str()).cloned().unwrap_or_default())
+    }
+
+    const A: &str = "git@github.com:org/a.git";
+    const B: &str = "git@github.com:org/b.git";
+    const C: &str = "git@github.com:org/c.git";
+
+    #[test]
+    fn test_non_transitive_imports_are_not_expanded() {
+        let mut graph = HashMap::new();
+        graph.insert(A, vec![import(B, "b1", false)]);
+
+        let resolved = resolve(vec![import(A, "a1", false)], &[], fake_producers(graph)).unwrap();
+
+        assert_eq!(vec![import(A, "a1", false)], resolved);
+    }
+
+    #[test]
+    fn test_transitive_imports_are_flattened_with_path() {
+        let mut graph = HashMap::new();
+        graph.insert(A, vec![import(B, "b1", true)]);
+        graph.insert(B, vec![import(C, "c1", false)]);
+
+        let resolved = resolve(vec![import(A, "a1", true)], &[], fake_producers(graph)).unwrap();
+
+        let urls: Vec<&str> = resolved.iter().map(|i| i.url.as_str()).collect();
+        assert_eq!(vec![A, B, C], urls);
+        assert_eq!(
+            vec![GitUrl::from_str(A).unwrap(), GitUrl::from_str(B).unwrap()],
+            resolved[2].via
+        );
+    }
+
+    #[test]
+    fn test_shared_dependency_at_same_commit_is_deduplicated() {
+        let mut graph = HashMap::new();
+        graph.insert(A, vec![import(C, "c1", false)]);
+        graph.insert(B, vec![import(C, "c1", false)]);
+
+        let resolved = resolve(
+            vec![import(A, "a1", true), import(B, "b1", true)],
+            &[],
+            fake_producers(graph),
+        )
+        .unwrap();
+
+        assert_eq!(3, resolved.len());
+    }
+
+    #[test]
+    fn test_conflicting_commits_are_rejected() {
+        let mut graph = HashMap::new();
+        graph.insert(A, vec![import(C, "c1", false)]);
+
+        let result = resolve(
+            vec![import(A, "a1", true), import(C, "c2", false)],
+            &[],
+            fake_producers(graph),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cycles_are_rejected() {
+        let mut graph = HashMap::new();
+        graph.insert(A, vec![import(B, "b1", true)]);
+        graph.insert(B, vec![import(A, "a1", true)]);
+
+        let result = resolve(vec![import(A, "a1", true)], &[], fake_producers(graph));
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Dependency cycle detected"));
+    }
+
+    #[test]
+    fn test_locked_transitive_imports_are_reused() {
+        let mut child = import(B, "b1", false);
+        child.via = vec![GitUrl::from_str(A).unwrap()];
+        let locked = vec![import(A, "a1", true), child.clone()];
+
+        let resolved = resolve(vec![import(A, "a1", true)], &locked, |_: &Import| {
+            Err(format_err!("producer metadata should not be fetched"))
+        })
+        .unwrap();
+
+        assert_eq!(locked, resolved);
+    }
+
+    #[test]
+    fn test_removed_direct_dependency_still_reached_transitively_is_kept() {
+        let mut graph = HashMap::new();
+        graph.insert(A, vec![import(C, "c1", false)]);
+        let direct = vec![import(A, "a1", true), import(C, "c1", false)];
+        let locked = resolve(direct, &[], fake_producers(graph.clone())).unwrap();
+        assert!(locked
+            .iter()
+            .any(|i| i.url.as_str() == C && i.via.is_empty()));
+
+        let resolved =
+            resolve(vec![import(A, "a1", true)], &locked, fake_producers(graph)).unwrap();
+
+        let mut expected = import(C, "c1", false);
+        expected.via = vec![GitUrl::from_str(A).unwrap()];
+        assert_eq!(vec![import(A, "a1", true), expected], resolved);
+    }
+
+    #[test]
+    fn test_removed_dependency_not_reached_otherwise_is_not_resolved_again() {
+        let mut graph = HashMap::new();
+        graph.insert(A, vec![import(B, "b1", false)]);
+        graph.insert(C, Vec::new());
+        let locked = resolve(
+            vec![import(A, "a1", true), import(B, "b1", false)],
+            &[],
+            fake_producers(graph.clone()),
+        )
+        .unwrap();
+        let locked: Vec<Import> = locked
+            .into_iter()
+            .chain(vec![import("git@github.com:org/d.git", "d1", false)])
+            .collect();
+
+        let mut fetched = Vec::new();
+        let mut producers = fake_producers(graph);
+        let resolved = resolve(
+            vec![
+                import(A, "a1", true),
+                import(B, "b1", false),
+                import(C, "c1", true),
+            ],
+            &locked,
+            |parent: &Import| {
+                fetched.push(parent.url.to_string());
+                producers(parent)
+            },
+        )
+        .unwrap();
+
+        assert_eq!(vec![C, A], fetched);
+        let urls: Vec<&str> = resolved.iter().map(|i| i.url.as_str()).collect();
+        assert_eq!(vec![A, B, C], urls);
+    }
+}
@@ -15,11 +15,14 @@
 */
 
 use super::Import;
+use crate::git_url::GitUrl;
 use crate::Result;
-use crate::{check, git, PROTOS_DIRECTORY};
+use crate::{check, git, util, PROTOS_DIRECTORY};
 use failure::format_err;
 use lazy_static::lazy_static;
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt::{self, Display, Formatter};
 use std::fs;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
@@ -28,44 +31,203 @@ lazy_static! {
     pub static ref PROTOS_OUTPUT_DIRECTORY: PathBuf = PathBuf::from("vendor/proto");
 }
 
-pub(super) fn vendor_import(import: &Import) -> Result<()> {
-    log::info!(
-        "Fetching proto files {} branch from git repo. Current: {}",
-        import.branch,
-        import.url
-    );
-    let repo = git::get_repo(&import.url, &import.branch, &import.commit)?;
-    let clone_location = repo.workdir().unwrap(); //Can unwrap safely as repository is not bare
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Provider {
+    pub(super) url: GitUrl,
+    pub(super) branch: String,
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.branch, self.url)
+    }
+}
+
+struct VendoredFile {
+    provider: Provider,
+    contents: Vec<u8>,
+}
+
+/// Every file to be written to the output directory, keyed by its path relative to
+/// PROTOS_OUTPUT_DIRECTORY, so that collisions can be found before anything is written.
+#[derive(Default)]
+pub(super) struct VendorPlan {
+    files: BTreeMap<PathBuf, Vec<VendoredFile>>,
+}
+
+impl VendorPlan {
+    pub(super) fn add_import(&mut self, import: &Import) -> Result<()> {
+        log::info!(
+            "Fetching proto files {} branch from git repo. Current: {}",
+            import.branch,
+            import.url
+        );
+        let repo = git::get_repo(&import.url, &import.branch, &import.commit)?;
+        let clone_location = repo.workdir().unwrap(); //Can unwrap safely as repository is not bare
 
-    let sanitised_path = import.url.sanitised_path();
+        let sanitised_path = import.url.sanitised_path();
 
-    let src_folder = create_src_folder_path(&clone_location, &sanitised_path);
-    let dest_folder = create_dest_folder_path(&sanitised_path)?;
+        let src_folder = create_src_folder_path(clone_location, &sanitised_path);
 
-    log::info!(
-        "calling check with {} and {}",
-        clone_location.display(),
-        import.url
-    );
-    check::run_checks(clone_location, &import.url)?;
+        log::info!(
+            "calling check with {} and {}",
+            clone_location.display(),
+            import.url
+        );
+        check::run_checks(clone_location, &import.url)?;
+
+        let provider = Provider {
+            url: import.url.clone(),
+            branch: import.branch.clone(),
+        };
+
+        for src_proto_file in find_protos(&src_folder)? {
+            let relative = src_proto_file.strip_prefix(&src_folder)?;
+            let contents = fs::read(&src_proto_file)?;
+            self.insert(
+                Path::new(&sanitised_path).join(relative),
+                provider.clone(),
+                contents,
+            );
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn insert(&mut self, path: PathBuf, provider: Provider, contents: Vec<u8>) {
+        self.files
+            .entry(path)
+            .or_default()
+            .push(VendoredFile { provider, contents });
+    }
+
+    /// Files as they will be written, with the dependency each is taken from.
+    pub(super) fn files(&self) -> impl Iterator<Item = (&Path, &Provider, &[u8])> {
+        self.files.iter().map(|(path, files)| {
+            (
+                path.as_path(),
+                &files[0].provider,
+                files[0].contents.as_slice(),
+            )
+        })
+    }
 
-    find_and_copy_protos(&src_folder, &dest_folder)
+    fn conflicts(&self) -> Vec<(&PathBuf, Vec<&Provider>)> {
+        self.files
+            .iter()
+            .filter(|(_, files)| files.len() > 1)
+            .map(|(path, files)| (path, files.iter().map(|f| &f.provider).collect()))
+            .collect()
+    }
+
+    pub(super) fn check_conflicts(&self) -> Result<()> {
+        let conflicts = self.conflicts();
+
+        for (path, providers) in conflicts.iter() {
+            let providers: Vec<String> = providers.iter().map(|p| p.to_string()).collect();
+            log::error!(
+                "{} is provided by more than one dependency: {}",
+                PROTOS_OUTPUT_DIRECTORY.join(path).display(),
+                providers.join(", ")
+            );
+        }
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(format_err!(
+                "{} vendored file(s) conflict between dependencies",
+                conflicts.len()
+            ))
+        }
+    }
+
+    /// Writes every file, returning their paths relative to the current directory. Files are
+    /// staged beside the output directory, which is swapped for them once all are written.
+    /// `commit` runs straight after the swap, which is undone if it fails.
+    pub(super) fn write<F>(&self, commit: F) -> Result<Vec<PathBuf>>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let dest_folder = create_dest_folder_path()?;
+        let staged = util::staging_path(&dest_folder, "tmp");
+        recover_output_directory(&dest_folder)?;
+        if staged.exists() {
+            fs::remove_dir_all(&staged)?;
+        }
+        fs::create_dir_all(&staged)?;
+
+        let mut written = Vec::new();
+        let result = self.files.iter().try_for_each(|(relative, files)| {
+            let dest = staged.join(relative);
+            fs::create_dir_all(dest.parent().unwrap())?;
+            fs::write(&dest, &files[0].contents)?;
+
+            log::debug!("Wrote {} from {}", dest.display(), files[0].provider);
+            written.push(PROTOS_OUTPUT_DIRECTORY.join(relative));
+            Ok(())
+        });
+        if let Err(e) = result.and_then(|_| swap_directory(&staged, &dest_folder, commit)) {
+            let _ = fs::remove_dir_all(&staged);
+            return Err(e);
+        }
+
+        Ok(written)
+    }
 }
 
-pub(super) fn prepare_output_directory() -> Result<()> {
-    if PROTOS_OUTPUT_DIRECTORY.exists() {
-        fs::remove_dir_all(PROTOS_OUTPUT_DIRECTORY.as_path())?;
+/// Replaces `dest` with `staged` and runs `commit`, putting the previous `dest` back if either
+/// fails.
+pub(crate) fn swap_directory<F>(staged: &Path, dest: &Path, commit: F) -> Result<()>
+where
+    F: FnOnce() -> Result<()>,
+{
+    let previous = util::staging_path(dest, "old");
+
+    if dest.exists() {
+        fs::rename(dest, &previous)?;
+    }
+    if let Err(e) = fs::rename(staged, dest) {
+        if previous.exists() {
+            fs::rename(&previous, dest)?;
+        }
+        return Err(e.into());
+    }
+    if let Err(e) = commit() {
+        fs::remove_dir_all(dest)?;
+        if previous.exists() {
+            fs::rename(&previous, dest)?;
+        }
+        return Err(e);
+    }
+    if previous.exists() {
+        fs::remove_dir_all(&previous)?;
     }
 
-    fs::create_dir_all(PROTOS_OUTPUT_DIRECTORY.as_path())?;
+    Ok(())
+}
+
+/// Restores the previous output directory if a crash left it moved aside mid swap.
+fn recover_output_directory(dest: &Path) -> Result<()> {
+    let previous = util::staging_path(dest, "old");
+
+    if previous.exists() {
+        if dest.exists() {
+            fs::remove_dir_all(&previous)?;
+        } else {
+            log::warn!(
+                "Restoring {} left by an interrupted install",
+                PROTOS_OUTPUT_DIRECTORY.display()
+            );
+            fs::rename(&previous, dest)?;
+        }
+    }
 
     Ok(())
 }
 
-fn create_dest_folder_path(repo: &str) -> Result<PathBuf> {
-    Ok(env::current_dir()?
-        .join(PROTOS_OUTPUT_DIRECTORY.as_path())
-        .join(repo))
+fn create_dest_folder_path() -> Result<PathBuf> {
+    Ok(env::current_dir()?.join(PROTOS_OUTPUT_DIRECTORY.as_path()))
 }
 
 fn create_src_folder_path<P: AsRef<Path>>(src_working_dir: P, url_path: &str) -> PathBuf {
@@ -75,7 +237,7 @@ fn create_src_folder_path<P: AsRef<Path>>(src_working_dir: P, url_path: &str) ->
         .join(url_path)
 }
 
-fn find_and_copy_protos(src_folder: &Path, dest_folder: &Path) -> Result<()> {
+fn find_protos(src_folder: &Path) -> Result<Vec<PathBuf>> {
     if !src_folder.exists() {
         return Err(format_err!(
             "Cannot find expected directory {}",
@@ -83,19 +245,110 @@ fn find_and_copy_protos(src_folder: &Path, dest_folder: &Path) -> Result<()> {
         ));
     }
 
+    let mut protos = Vec::new();
     for entry in WalkDir::new(src_folder) {
         let entry = entry?;
         if entry.metadata()?.is_file() && entry.file_name().to_string_lossy().ends_with(".proto") {
-            let src_proto_file = entry.path();
-            let relative = src_proto_file.strip_prefix(src_folder)?;
-            let dest = dest_folder.join(relative);
-            fs::create_dir_all(dest.parent().unwrap())?;
+            protos.push(entry.into_path());
+        }
+    }
 
-            fs::copy(src_proto_file, &dest)?;
+    Ok(protos)
+}
 
-            log::debug!("Copied {} to {}", src_proto_file.display(), dest.display());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn provider(url: &str) -> Provider {
+        Provider {
+            url: GitUrl::from_str(url).unwrap(),
+            branch: String::from("master"),
         }
     }
 
-    Ok(())
+    #[test]
+    fn test_no_conflicts_for_distinct_paths() {
+        let mut plan = VendorPlan::default();
+        plan.insert(
+            PathBuf::from("foo/bar/a.proto"),
+            provider("git@github.com:foo/bar.git"),
+            Vec::new(),
+        );
+        plan.insert(
+            PathBuf::from("foo/baz/a.proto"),
+            provider("git@github.com:foo/baz.git"),
+            Vec::new(),
+        );
+
+        assert!(plan.conflicts().is_empty());
+        assert!(plan.check_conflicts().is_ok());
+    }
+
+    #[test]
+    fn test_conflicts_reported_for_colliding_sanitised_paths() {
+        let first = provider("git@github.com:Foo/Foo-Bar.git");
+        let second = provider("git@github.com:foo/foobar.git");
+        assert_eq!(first.url.sanitised_path(), second.url.sanitised_path());
+
+        let mut plan = VendorPlan::default();
+        for p in vec![first.clone(), second.clone()] {
+            plan.insert(
+                Path::new(&p.url.sanitised_path()).join("a.proto"),
+                p,
+                Vec::new(),
+            );
+        }
+
+        let conflicts = plan.conflicts();
+        assert_eq!(1, conflicts.len());
+        assert_eq!(&PathBuf::from("foo/foobar/a.proto"), conflicts[0].0);
+        assert_eq!(vec![&first, &second], conflicts[0].1);
+        assert!(plan.check_conflicts().is_err());
+    }
+
+    #[test]
+    fn test_swap_directory_replaces_previous_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("proto");
+        let staged = util::staging_path(&dest, "tmp");
+        fs::create_dir_all(dest.join("old")).unwrap();
+        fs::create_dir_all(staged.join("new")).unwrap();
+
+        swap_directory(&staged, &dest, || Ok(())).unwrap();
+
+        assert!(dest.join("new").is_dir());
+        assert!(!dest.join("old").exists());
+        assert!(!staged.exists());
+        assert!(!util::staging_path(&dest, "old").exists());
+    }
+
+    #[test]
+    fn test_swap_directory_is_undone_when_commit_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("proto");
+        let staged = util::staging_path(&dest, "tmp");
+        fs::create_dir_all(dest.join("old")).unwrap();
+        fs::create_dir_all(staged.join("new")).unwrap();
+
+        let result = swap_directory(&staged, &dest, || Err(format_err!("Cannot write lock")));
+
+        assert!(result.is_err());
+        assert!(dest.join("old").is_dir());
+        assert!(!dest.join("new").exists());
+        assert!(!util::staging_path(&dest, "old").exists());
+    }
+
+    #[test]
+    fn test_interrupted_swap_is_recovered() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("proto");
+        fs::create_dir_all(util::staging_path(&dest, "old").join("a")).unwrap();
+
+        recover_output_directory(&dest).unwrap();
+
+        assert!(dest.join("a").is_dir());
+        assert!(!util::staging_path(&dest, "old").exists());
+    }
 }
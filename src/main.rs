@@ -14,9 +14,16 @@
  * limitations under the License.
 */
 
+use failure::format_err;
 use human_panic::setup_panic;
 use log;
 use protovend::git_url::GitUrl;
+use protovend::summary::Summary;
+use protovend::{GraphFormat, OutputFormat};
+use serde_json::json;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use structopt::clap::ArgGroup;
 use structopt::StructOpt;
 
@@ -29,6 +36,9 @@ struct Protovend {
     ///Prints debug logs. Used for diagnostics only.
     #[structopt(long, group = "level")]
     debug: bool,
+    ///Output format: text, or json to print a single result on stdout with logs on stderr.
+    #[structopt(long, default_value = "text")]
+    output: Output,
     #[structopt(subcommand)]
     sub: Subcommand,
 }
@@ -43,27 +53,164 @@ enum Subcommand {
         url: GitUrl,
         #[structopt(short, long, default_value = "master")]
         branch: String,
+        ///Also vendor the dependencies declared in the repo's own protovend metadata file
+        #[structopt(long)]
+        transitive: bool,
     },
     ///Update one or all repos in protovend metadata file to latest version
-    Update { repo: Option<GitUrl> },
+    Update {
+        repo: Option<GitUrl>,
+        ///Fail without updating if the new version contains wire or source breaking changes
+        #[structopt(long)]
+        deny_breaking: bool,
+        ///Show the changes to vendored protos without updating the lock or vendored protos
+        #[structopt(long)]
+        dry_run: bool,
+        ///Write a Markdown changelog of upstream commits touching each repo's protos to this file
+        #[structopt(long, parse(from_os_str))]
+        changelog: Option<PathBuf>,
+    },
     ///Install copies of protofiles declared in projects metadata file
     Install {},
-    ///Delete all locally cached repos stored in protovend folder
-    Cleanup {},
+    ///List repos with newer commits than those locked, exiting with code 2 if there are any
+    Outdated {},
+    ///List declared and locked repos with their commit, vendored file count and sync status
+    List {},
+    ///Show the files vendored for each repo and which repo each of their imports comes from
+    Tree {},
+    ///Show which repo and commit a vendored file, proto package or message comes from
+    Why {
+        ///Vendored file, proto package or fully qualified message name
+        query: String,
+    },
+    ///Print the graph of projects and the repos they vendor, combining several projects if given
+    Graph {
+        ///Format to print the graph in: dot or json
+        #[structopt(long, default_value = "dot")]
+        format: GraphFormat,
+        ///Directories of the projects to include, defaulting to the current directory
+        #[structopt(parse(from_os_str))]
+        paths: Vec<PathBuf>,
+    },
+    ///Find the projects vendoring each repo by scanning directories of checked out repos
+    Scan {
+        ///Directories to search for protovend projects
+        #[structopt(required = true, parse(from_os_str))]
+        dirs: Vec<PathBuf>,
+        ///Only report projects vendoring this repo
+        #[structopt(long)]
+        producer: Option<GitUrl>,
+        ///Fetch each vendored repo to count the commits each project is behind
+        #[structopt(long)]
+        fetch: bool,
+    },
+    ///Delete locally cached repos stored in protovend folder, reporting the space freed
+    Cleanup {
+        ///Only delete repos not used for this long, e.g. 12h, 30d or 2w
+        #[structopt(long)]
+        stale: Option<String>,
+        ///Only delete repos not locked by the given projects, or the current directory
+        #[structopt(long)]
+        unused: bool,
+        ///Project directories whose locked repos --unused keeps
+        #[structopt(parse(from_os_str), requires = "unused")]
+        projects: Vec<PathBuf>,
+        ///Report the repos that would be deleted and their size without deleting them
+        #[structopt(long)]
+        dry_run: bool,
+    },
     ///Lint function to ensure proto files and directories are valid for the protovend tool
-    Lint {},
+    Lint {
+        ///Git url of the project, when it cannot be found from the protovend metadata file or git remotes
+        #[structopt(long)]
+        url: Option<GitUrl>,
+        ///Git ref to compare protos against, failing on backward incompatible changes
+        #[structopt(long)]
+        breaking_against: Option<String>,
+        ///Format to report results in: text, json, sarif or github
+        #[structopt(long, default_value = "text")]
+        format: OutputFormat,
+        ///Move misplaced proto files into the expected directory, updating imports of them
+        #[structopt(long)]
+        fix: bool,
+        ///Print the changes --fix would make without making them
+        #[structopt(long, requires = "fix")]
+        dry_run: bool,
+    },
+}
+
+impl Subcommand {
+    fn name(&self) -> &'static str {
+        match self {
+            Subcommand::Init {} => "init",
+            Subcommand::Add { .. } => "add",
+            Subcommand::Update { .. } => "update",
+            Subcommand::Install {} => "install",
+            Subcommand::Outdated {} => "outdated",
+            Subcommand::List {} => "list",
+            Subcommand::Tree {} => "tree",
+            Subcommand::Why { .. } => "why",
+            Subcommand::Graph { .. } => "graph",
+            Subcommand::Scan { .. } => "scan",
+            Subcommand::Cleanup { .. } => "cleanup",
+            Subcommand::Lint { .. } => "lint",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Text,
+    Json,
 }
 
-fn setup_logger(level: log::LevelFilter) -> std::result::Result<(), fern::InitError> {
+impl FromStr for Output {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> protovend::Result<Self> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(format_err!("Unknown output {}; expected text or json", s)),
+        }
+    }
+}
+
+/// Warnings and errors logged while running a command.
+type Captured = Arc<Mutex<Vec<(log::Level, String)>>>;
+
+fn setup_logger(
+    level: log::LevelFilter,
+    to_stderr: bool,
+    captured: Captured,
+) -> std::result::Result<(), fern::InitError> {
+    let print = fern::Dispatch::new().format(|out, message, record| {
+        out.finish(format_args!("({}) {}", record.level(), message))
+    });
+    // Keep stdout clean when it carries machine readable output
+    let print = if to_stderr {
+        print.chain(std::io::stderr())
+    } else {
+        print.chain(std::io::stdout())
+    };
+    let capture = fern::Dispatch::new()
+        .level(log::LevelFilter::Warn)
+        .chain(fern::Output::call(move |record| {
+            captured
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()))
+        }));
+
     fern::Dispatch::new()
-        .format(|out, message, record| out.finish(format_args!("({}) {}", record.level(), message)))
         .level(level)
-        .chain(std::io::stdout())
+        .chain(print)
+        .chain(capture)
         .apply()?;
     Ok(())
 }
 
-fn run_command(opts: Protovend) -> protovend::Result<()> {
+fn run_command(opts: Protovend, captured: Captured) -> protovend::Result<Summary> {
     let level = if opts.debug {
         log::LevelFilter::Debug
     } else if opts.warning {
@@ -72,26 +219,141 @@ fn run_command(opts: Protovend) -> protovend::Result<()> {
         log::LevelFilter::Info
     };
 
-    setup_logger(level)?;
+    let machine_readable = match &opts.sub {
+        Subcommand::Lint { format, .. } => format.is_machine_readable(),
+        Subcommand::Graph { .. } => true,
+        _ => false,
+    };
+    if machine_readable && opts.output == Output::Json {
+        return Err(format_err!(
+            "The {} output format cannot be combined with --output json",
+            opts.sub.name()
+        ));
+    }
+
+    setup_logger(
+        level,
+        machine_readable || opts.output == Output::Json,
+        captured,
+    )?;
 
     match opts.sub {
         Subcommand::Init {} => protovend::init(),
-        Subcommand::Add { url, branch } => protovend::add(url, branch),
-        Subcommand::Update { repo } => protovend::update(repo),
+        Subcommand::Add {
+            url,
+            branch,
+            transitive,
+        } => protovend::add(url, branch, transitive),
+        Subcommand::Update {
+            repo,
+            deny_breaking,
+            dry_run,
+            changelog,
+        } => protovend::update(repo, deny_breaking, dry_run, changelog),
         Subcommand::Install {} => protovend::install(),
-        Subcommand::Cleanup {} => protovend::cleanup(),
-        Subcommand::Lint {} => protovend::lint(),
+        Subcommand::Outdated {} => protovend::outdated(),
+        Subcommand::List {} => protovend::list(),
+        Subcommand::Tree {} => protovend::tree(),
+        Subcommand::Why { query } => protovend::why(query),
+        Subcommand::Graph { format, paths } => protovend::graph(paths, format),
+        Subcommand::Scan {
+            dirs,
+            producer,
+            fetch,
+        } => protovend::scan(dirs, producer, fetch),
+        Subcommand::Cleanup {
+            stale,
+            unused,
+            projects,
+            dry_run,
+        } => protovend::cleanup(stale, unused, projects, dry_run),
+        Subcommand::Lint {
+            url,
+            breaking_against,
+            format,
+            fix,
+            dry_run,
+        } => protovend::lint(url, breaking_against, format, fix, dry_run),
     }
 }
 
+fn to_json(
+    command: &str,
+    summary: &Summary,
+    error: Option<&failure::Error>,
+    captured: &Captured,
+) -> String {
+    let captured = captured.lock().unwrap();
+    let messages = |level: log::Level| -> Vec<&String> {
+        captured
+            .iter()
+            .filter(|(l, _)| *l == level)
+            .map(|(_, message)| message)
+            .collect()
+    };
+    let mut errors: Vec<String> = messages(log::Level::Error).into_iter().cloned().collect();
+
+    if let Some(error) = error {
+        // The logger may not have been set up to capture the error
+        if errors.is_empty() {
+            errors.push(format!("Exiting early: {}", error));
+        }
+    }
+
+    let mut result = json!({
+        "command": command,
+        "success": error.is_none(),
+        "dependencies": summary.dependencies,
+        "files_written": summary.files_written,
+        "warnings": messages(log::Level::Warn),
+        "errors": errors,
+    });
+    if !summary.consumers.is_empty() {
+        result["consumers"] = json!(summary.consumers);
+    }
+    if !summary.listed.is_empty() {
+        result["listed"] = json!(summary.listed);
+    }
+    if !summary.tree.is_empty() {
+        result["tree"] = json!(summary.tree);
+    }
+    if !summary.traced.is_empty() {
+        result["traced"] = json!(summary.traced);
+    }
+    if !summary.lint_results.is_empty() {
+        result["lint_results"] = json!(summary.lint_results);
+    }
+    result.to_string()
+}
+
 fn main() {
     setup_panic!();
 
-    std::process::exit(match run_command(Protovend::from_args()) {
-        Ok(_) => 0,
-        Err(error) => {
-            log::error!("Exiting early: {}", error);
-            1
+    let opts = Protovend::from_args();
+    let output = opts.output;
+    let command = opts.sub.name();
+    let captured = Captured::default();
+
+    let (summary, error) = match run_command(opts, captured.clone()) {
+        Ok(summary) => {
+            let error = summary.error();
+            (summary, error)
         }
-    });
+        Err(error) => (Summary::default(), Some(error)),
+    };
+    if let Some(error) = &error {
+        log::error!("Exiting early: {}", error);
+    }
+    let code = match &error {
+        Some(_) => 1,
+        // Lets scheduled jobs tell outdated dependencies apart from failures
+        None if summary.outdated => 2,
+        None => 0,
+    };
+
+    if output == Output::Json {
+        println!("{}", to_json(command, &summary, error.as_ref(), &captured));
+    }
+
+    std::process::exit(code);
 }
@@ -0,0 +1,271 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use crate::Result;
+use failure::Fail;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+mod lexer;
+mod parser;
+
+/// Files shipped with protoc itself, which are always available to import.
+pub const WELL_KNOWN_TYPES: &[&str] = &[
+    "google/protobuf/any.proto",
+    "google/protobuf/api.proto",
+    "google/protobuf/compiler/plugin.proto",
+    "google/protobuf/descriptor.proto",
+    "google/protobuf/duration.proto",
+    "google/protobuf/empty.proto",
+    "google/protobuf/field_mask.proto",
+    "google/protobuf/source_context.proto",
+    "google/protobuf/struct.proto",
+    "google/protobuf/timestamp.proto",
+    "google/protobuf/type.proto",
+    "google/protobuf/wrappers.proto",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Fail)]
+#[fail(display = "{}: {}", position, message)]
+pub struct ParseError {
+    pub position: Position,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Syntax {
+    #[default]
+    Proto2,
+    Proto3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportKind {
+    Default,
+    Public,
+    Weak,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub path: String,
+    pub kind: ImportKind,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub name: String,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtoOption {
+    pub name: String,
+    pub value: String,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Label {
+    None,
+    Optional,
+    Required,
+    Repeated,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub number: i64,
+    pub label: Label,
+    /// Type as written in the source, with maps normalised to `map<K, V>`.
+    pub type_name: String,
+    pub oneof: Option<String>,
+    pub options: Vec<ProtoOption>,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Oneof {
+    pub name: String,
+    pub position: Position,
+}
+
+/// An inclusive range of reserved or extension numbers.
+pub type Range = (i64, i64);
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Message {
+    pub name: String,
+    pub fields: Vec<Field>,
+    pub oneofs: Vec<Oneof>,
+    pub messages: Vec<Message>,
+    pub enums: Vec<Enum>,
+    pub extends: Vec<Extend>,
+    pub options: Vec<ProtoOption>,
+    pub reserved_ranges: Vec<Range>,
+    pub reserved_names: Vec<String>,
+    pub extension_ranges: Vec<Range>,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumValue {
+    pub name: String,
+    pub number: i64,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Enum {
+    pub name: String,
+    pub values: Vec<EnumValue>,
+    pub options: Vec<ProtoOption>,
+    pub reserved_ranges: Vec<Range>,
+    pub reserved_names: Vec<String>,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rpc {
+    pub name: String,
+    pub input_type: String,
+    pub output_type: String,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Service {
+    pub name: String,
+    pub rpcs: Vec<Rpc>,
+    pub options: Vec<ProtoOption>,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extend {
+    pub extendee: String,
+    pub fields: Vec<Field>,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProtoFile {
+    pub syntax: Syntax,
+    pub package: Option<Package>,
+    pub imports: Vec<Import>,
+    pub options: Vec<ProtoOption>,
+    pub messages: Vec<Message>,
+    pub enums: Vec<Enum>,
+    pub services: Vec<Service>,
+    pub extends: Vec<Extend>,
+}
+
+pub fn parse(contents: &str) -> std::result::Result<ProtoFile, ParseError> {
+    parser::parse(&lexer::tokenize(contents)?)
+}
+
+pub fn parse_file(path: &Path) -> Result<std::result::Result<ProtoFile, ParseError>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(parse(&contents))
+}
+
+/// Import paths declared in a file. Unlike `parse` this tolerates files that are otherwise
+/// invalid, as long as they can be tokenized.
+pub fn imports(contents: &str) -> Vec<String> {
+    spanned_imports(contents)
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect()
+}
+
+/// Import paths declared in a file, with the position of the string literal holding each.
+pub fn spanned_imports(contents: &str) -> Vec<(Position, String)> {
+    let tokens = match lexer::tokenize(contents) {
+        Ok(tokens) => tokens,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut imports = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i].token {
+            lexer::Token::Symbol('{') => depth += 1,
+            lexer::Token::Symbol('}') => depth -= 1,
+            lexer::Token::Ident(ident) if ident == "import" && depth == 0 => {
+                let mut j = i + 1;
+                if let Some(lexer::Token::Ident(modifier)) = tokens.get(j).map(|t| &t.token) {
+                    if modifier == "public" || modifier == "weak" {
+                        j += 1;
+                    }
+                }
+                if let Some(lexer::Token::Str(path)) = tokens.get(j).map(|t| &t.token) {
+                    imports.push((tokens[j].position, path.clone()));
+                    i = j;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    imports
+}
+
+pub fn is_well_known(import: &str) -> bool {
+    WELL_KNOWN_TYPES.contains(&import)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imports_are_extracted() {
+        let contents = "syntax = \"proto3\";\
+                        \nimport \"google/protobuf/timestamp.proto\";\
+                        \n  import public \"org/repo/a.proto\" ;\
+                        \nimport weak \"org/repo/b.proto\";\
+                        \n// import \"org/repo/commented.proto\";\
+                        \nmessage Foo {}";
+
+        assert_eq!(
+            vec![
+                "google/protobuf/timestamp.proto",
+                "org/repo/a.proto",
+                "org/repo/b.proto"
+            ],
+            imports(contents)
+        );
+    }
+}
@@ -0,0 +1,310 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::{ParseError, Position};
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Symbol(char),
+}
+
+impl Token {
+    pub fn describe(&self) -> String {
+        match self {
+            Token::Ident(s) => format!("'{}'", s),
+            Token::Number(n) => format!("number {}", n),
+            Token::Str(s) => format!("string \"{}\"", s),
+            Token::Symbol(c) => format!("'{}'", c),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned {
+    pub token: Token,
+    pub position: Position,
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+pub fn tokenize(contents: &str) -> Result<Vec<Spanned>, ParseError> {
+    let mut lexer = Lexer {
+        chars: contents.chars().peekable(),
+        line: 1,
+        column: 1,
+    };
+    let mut tokens = Vec::new();
+
+    while let Some(token) = lexer.next_token()? {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+impl<'a> Lexer<'a> {
+    fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, position: Position, message: String) -> ParseError {
+        ParseError { position, message }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), ParseError> {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') => {
+                    let start = self.position();
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    match lookahead.peek() {
+                        Some('/') => {
+                            while let Some(c) = self.bump() {
+                                if c == '\n' {
+                                    break;
+                                }
+                            }
+                        }
+                        Some('*') => {
+                            self.bump();
+                            self.bump();
+                            let mut previous = ' ';
+                            loop {
+                                match self.bump() {
+                                    Some('/') if previous == '*' => break,
+                                    Some(c) => previous = c,
+                                    None => {
+                                        return Err(
+                                            self.error(start, "unterminated comment".to_owned())
+                                        )
+                                    }
+                                }
+                            }
+                        }
+                        _ => return Ok(()),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Spanned>, ParseError> {
+        self.skip_whitespace_and_comments()?;
+
+        let position = self.position();
+        let c = match self.chars.peek() {
+            Some(c) => *c,
+            None => return Ok(None),
+        };
+
+        let token = if c.is_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = self.chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    ident.push(c);
+                    self.bump();
+                } else {
+                    break;
+                }
+            }
+            Token::Ident(ident)
+        } else if c.is_ascii_digit() || (c == '.' && self.next_is_digit()) {
+            Token::Number(self.number())
+        } else if c == '"' || c == '\'' {
+            Token::Str(self.string(position)?)
+        } else if "=;{}[]()<>,.-+:/".contains(c) {
+            self.bump();
+            Token::Symbol(c)
+        } else {
+            return Err(self.error(position, format!("unexpected character '{}'", c)));
+        };
+
+        Ok(Some(Spanned { token, position }))
+    }
+
+    fn next_is_digit(&self) -> bool {
+        let mut lookahead = self.chars.clone();
+        lookahead.next();
+        lookahead.peek().is_some_and(|c| c.is_ascii_digit())
+    }
+
+    fn number(&mut self) -> String {
+        let mut number = String::new();
+        let is_hex = {
+            let mut lookahead = self.chars.clone();
+            lookahead.next() == Some('0') && matches!(lookahead.next(), Some('x') | Some('X'))
+        };
+        while let Some(&c) = self.chars.peek() {
+            let exponent_sign = !is_hex
+                && (c == '+' || c == '-')
+                && (number.ends_with('e') || number.ends_with('E'));
+            if c.is_alphanumeric() || c == '.' || c == '_' || exponent_sign {
+                number.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        number
+    }
+
+    fn string(&mut self, start: Position) -> Result<String, ParseError> {
+        let quote = self.bump().unwrap();
+        let mut value = String::new();
+
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => return Ok(value),
+                Some('\n') | None => {
+                    return Err(self.error(start, "unterminated string literal".to_owned()))
+                }
+                Some('\\') => {
+                    let escape_position = self.position();
+                    match self.bump() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('r') => value.push('\r'),
+                        Some('a') => value.push('\u{7}'),
+                        Some('b') => value.push('\u{8}'),
+                        Some('f') => value.push('\u{c}'),
+                        Some('v') => value.push('\u{b}'),
+                        Some('?') => value.push('?'),
+                        Some(c @ '\\') | Some(c @ '\'') | Some(c @ '"') => value.push(c),
+                        Some('x') | Some('X') => value.push(self.escaped_char(16, 2)),
+                        Some('u') => value.push(self.escaped_char(16, 4)),
+                        Some('U') => value.push(self.escaped_char(16, 8)),
+                        Some(c) if c.is_digit(8) => {
+                            let mut digits = c.to_string();
+                            digits.push_str(&self.digits(8, 2));
+                            value.push(to_char(&digits, 8));
+                        }
+                        _ => {
+                            return Err(
+                                self.error(escape_position, "invalid escape sequence".to_owned())
+                            )
+                        }
+                    }
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn digits(&mut self, radix: u32, max: usize) -> String {
+        let mut digits = String::new();
+        while digits.len() < max {
+            match self.chars.peek() {
+                Some(c) if c.is_digit(radix) => {
+                    digits.push(*c);
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+        digits
+    }
+
+    fn escaped_char(&mut self, radix: u32, max: usize) -> char {
+        let digits = self.digits(radix, max);
+        to_char(&digits, radix)
+    }
+}
+
+fn to_char(digits: &str, radix: u32) -> char {
+    u32::from_str_radix(digits, radix)
+        .ok()
+        .and_then(std::char::from_u32)
+        .unwrap_or(std::char::REPLACEMENT_CHARACTER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(contents: &str) -> Vec<Token> {
+        tokenize(contents)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.token)
+            .collect()
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        assert_eq!(
+            vec![
+                Token::Ident("message".to_owned()),
+                Token::Ident("Foo".to_owned()),
+                Token::Symbol('{'),
+                Token::Symbol('}'),
+            ],
+            tokens("// leading\nmessage /* inline */ Foo {\n}")
+        );
+    }
+
+    #[test]
+    fn test_literals_are_tokenized() {
+        assert_eq!(
+            vec![
+                Token::Number("0x1F".to_owned()),
+                Token::Number("1.5e-3".to_owned()),
+                Token::Str("a\"b\n".to_owned()),
+                Token::Str("it's".to_owned()),
+            ],
+            tokens("0x1F 1.5e-3 \"a\\\"b\\n\" 'it\\'s'")
+        );
+    }
+
+    #[test]
+    fn test_positions_are_tracked() {
+        let tokens = tokenize("syntax\n  = \"proto3\";").unwrap();
+        assert_eq!(Position { line: 2, column: 3 }, tokens[1].position);
+    }
+
+    #[test]
+    fn test_unterminated_string_is_an_error() {
+        let error = tokenize("import \"foo.proto;\n").unwrap_err();
+        assert_eq!(Position { line: 1, column: 8 }, error.position);
+    }
+}
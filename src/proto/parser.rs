@@ -0,0 +1,903 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use super::lexer::{Spanned, Token};
+use super::{
+    Enum, EnumValue, Extend, Field, Import, ImportKind, Label, Message, Oneof, Package, ParseError,
+    Position, ProtoFile, ProtoOption, Range, Rpc, Service, Syntax,
+};
+
+const MAX_FIELD_NUMBER: i64 = 536_870_911;
+const MAX_ENUM_NUMBER: i64 = 2_147_483_647;
+
+type ParseResult<A> = Result<A, ParseError>;
+
+struct Parser<'a> {
+    tokens: &'a [Spanned],
+    index: usize,
+}
+
+pub fn parse(tokens: &[Spanned]) -> ParseResult<ProtoFile> {
+    Parser { tokens, index: 0 }.file()
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.index).map(|t| &t.token)
+    }
+
+    fn peek_ahead(&self, n: usize) -> Option<&'a Token> {
+        self.tokens.get(self.index + n).map(|t| &t.token)
+    }
+
+    fn position(&self) -> Position {
+        match self.tokens.get(self.index) {
+            Some(t) => t.position,
+            None => self
+                .tokens
+                .last()
+                .map(|t| Position {
+                    line: t.position.line,
+                    column: t.position.column + 1,
+                })
+                .unwrap_or(Position { line: 1, column: 1 }),
+        }
+    }
+
+    fn error<A>(&self, expected: &str) -> ParseResult<A> {
+        let found = match self.peek() {
+            Some(token) => token.describe(),
+            None => "end of file".to_owned(),
+        };
+        Err(ParseError {
+            position: self.position(),
+            message: format!("expected {}, found {}", expected, found),
+        })
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.index += 1;
+        token
+    }
+
+    fn is_symbol(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Symbol(c))
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(ident)) => ident == keyword,
+            _ => false,
+        }
+    }
+
+    fn eat_symbol(&mut self, c: char) -> bool {
+        if self.is_symbol(c) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, c: char) -> ParseResult<()> {
+        if self.eat_symbol(c) {
+            Ok(())
+        } else {
+            self.error(&format!("'{}'", c))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> ParseResult<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            self.error(&format!("'{}'", keyword))
+        }
+    }
+
+    fn ident(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                self.index += 1;
+                Ok(ident.clone())
+            }
+            _ => self.error("identifier"),
+        }
+    }
+
+    /// A dotted name, optionally fully qualified with a leading dot.
+    fn full_ident(&mut self) -> ParseResult<String> {
+        let mut name = String::new();
+        if self.eat_symbol('.') {
+            name.push('.');
+        }
+        name.push_str(&self.ident()?);
+        while self.eat_symbol('.') {
+            name.push('.');
+            name.push_str(&self.ident()?);
+        }
+        Ok(name)
+    }
+
+    fn string(&mut self) -> ParseResult<String> {
+        let mut value = match self.peek() {
+            Some(Token::Str(s)) => s.clone(),
+            _ => return self.error("string literal"),
+        };
+        self.index += 1;
+        // Adjacent string literals are concatenated, as in C.
+        while let Some(Token::Str(s)) = self.peek() {
+            value.push_str(s);
+            self.index += 1;
+        }
+        Ok(value)
+    }
+
+    fn integer(&mut self) -> ParseResult<i64> {
+        let negative = self.eat_symbol('-');
+        let position = self.position();
+        let literal = match self.peek() {
+            Some(Token::Number(n)) => n,
+            _ => return self.error("integer"),
+        };
+        let value = parse_integer(literal).ok_or_else(|| ParseError {
+            position,
+            message: format!("invalid integer {}", literal),
+        })?;
+        self.index += 1;
+        Ok(if negative { -value } else { value })
+    }
+
+    fn file(&mut self) -> ParseResult<ProtoFile> {
+        let mut file = ProtoFile::default();
+
+        if self.is_keyword("syntax") || self.is_keyword("edition") {
+            let keyword = self.ident()?;
+            self.expect_symbol('=')?;
+            let position = self.position();
+            let value = self.string()?;
+            self.expect_symbol(';')?;
+            file.syntax = match (keyword.as_str(), value.as_str()) {
+                ("syntax", "proto2") => Syntax::Proto2,
+                ("syntax", "proto3") | ("edition", _) => Syntax::Proto3,
+                _ => {
+                    return Err(ParseError {
+                        position,
+                        message: format!("unrecognized syntax \"{}\"", value),
+                    })
+                }
+            };
+        }
+
+        while self.peek().is_some() {
+            let position = self.position();
+            if self.eat_symbol(';') {
+                continue;
+            } else if self.eat_keyword("import") {
+                let kind = if self.eat_keyword("public") {
+                    ImportKind::Public
+                } else if self.eat_keyword("weak") {
+                    ImportKind::Weak
+                } else {
+                    ImportKind::Default
+                };
+                let path = self.string()?;
+                self.expect_symbol(';')?;
+                file.imports.push(Import {
+                    path,
+                    kind,
+                    position,
+                });
+            } else if self.eat_keyword("package") {
+                if file.package.is_some() {
+                    return Err(ParseError {
+                        position,
+                        message: "multiple package declarations".to_owned(),
+                    });
+                }
+                let name = self.full_ident()?;
+                self.expect_symbol(';')?;
+                file.package = Some(Package { name, position });
+            } else if self.is_keyword("option") {
+                file.options.push(self.option_statement()?);
+            } else if self.is_keyword("message") {
+                file.messages.push(self.message()?);
+            } else if self.is_keyword("enum") {
+                file.enums.push(self.enumeration()?);
+            } else if self.is_keyword("service") {
+                file.services.push(self.service()?);
+            } else if self.is_keyword("extend") {
+                file.extends.push(self.extend()?);
+            } else {
+                return self.error("top-level declaration");
+            }
+        }
+
+        Ok(file)
+    }
+
+    fn option_statement(&mut self) -> ParseResult<ProtoOption> {
+        let position = self.position();
+        self.expect_keyword("option")?;
+        let option = self.option_body(position)?;
+        self.expect_symbol(';')?;
+        Ok(option)
+    }
+
+    fn option_body(&mut self, position: Position) -> ParseResult<ProtoOption> {
+        let name = self.option_name()?;
+        self.expect_symbol('=')?;
+        let value = self.constant()?;
+        Ok(ProtoOption {
+            name,
+            value,
+            position,
+        })
+    }
+
+    fn option_name(&mut self) -> ParseResult<String> {
+        let mut name = String::new();
+        loop {
+            if self.eat_symbol('(') {
+                name.push('(');
+                name.push_str(&self.full_ident()?);
+                self.expect_symbol(')')?;
+                name.push(')');
+            } else {
+                name.push_str(&self.ident()?);
+            }
+            if self.eat_symbol('.') {
+                name.push('.');
+            } else {
+                return Ok(name);
+            }
+        }
+    }
+
+    fn constant(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(Token::Str(_)) => self.string(),
+            Some(Token::Symbol('{')) => self.aggregate(),
+            Some(Token::Symbol('-')) | Some(Token::Symbol('+')) => {
+                let sign = if self.eat_symbol('-') {
+                    "-"
+                } else {
+                    self.index += 1;
+                    ""
+                };
+                match self.next() {
+                    Some(Token::Number(n)) => Ok(format!("{}{}", sign, n)),
+                    Some(Token::Ident(i)) if i == "inf" || i == "nan" => {
+                        Ok(format!("{}{}", sign, i))
+                    }
+                    _ => {
+                        self.index -= 1;
+                        self.error("number")
+                    }
+                }
+            }
+            Some(Token::Number(n)) => {
+                self.index += 1;
+                Ok(n.clone())
+            }
+            Some(Token::Ident(_)) | Some(Token::Symbol('.')) => self.full_ident(),
+            _ => self.error("constant"),
+        }
+    }
+
+    /// Text format message literals are only ever used as option values, so they are
+    /// kept as raw text rather than parsed.
+    fn aggregate(&mut self) -> ParseResult<String> {
+        let mut depth = 0;
+        let mut value = String::new();
+        loop {
+            let token = match self.next() {
+                Some(token) => token,
+                None => return self.error("'}'"),
+            };
+            match token {
+                Token::Symbol('{') | Token::Symbol('<') => depth += 1,
+                Token::Symbol('}') | Token::Symbol('>') => depth -= 1,
+                _ => {}
+            }
+            if !value.is_empty() {
+                value.push(' ');
+            }
+            match token {
+                Token::Ident(s) | Token::Number(s) => value.push_str(s),
+                Token::Str(s) => value.push_str(&format!("{:?}", s)),
+                Token::Symbol(c) => value.push(*c),
+            }
+            if depth == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn field_options(&mut self) -> ParseResult<Vec<ProtoOption>> {
+        let mut options = Vec::new();
+        if self.eat_symbol('[') {
+            loop {
+                let position = self.position();
+                options.push(self.option_body(position)?);
+                if !self.eat_symbol(',') {
+                    break;
+                }
+            }
+            self.expect_symbol(']')?;
+        }
+        Ok(options)
+    }
+
+    fn message(&mut self) -> ParseResult<Message> {
+        let position = self.position();
+        self.expect_keyword("message")?;
+        let name = self.ident()?;
+        let mut message = Message {
+            name,
+            position,
+            ..Message::default()
+        };
+        self.message_body(&mut message)?;
+        Ok(message)
+    }
+
+    fn message_body(&mut self, message: &mut Message) -> ParseResult<()> {
+        self.expect_symbol('{')?;
+        loop {
+            if self.eat_symbol('}') {
+                return Ok(());
+            } else if self.peek().is_none() {
+                return self.error("'}'");
+            } else if self.eat_symbol(';') {
+                continue;
+            } else if self.is_keyword("option") {
+                message.options.push(self.option_statement()?);
+            } else if self.is_declaration("message") {
+                message.messages.push(self.message()?);
+            } else if self.is_declaration("enum") {
+                message.enums.push(self.enumeration()?);
+            } else if self.is_declaration("extend") {
+                message.extends.push(self.extend()?);
+            } else if self.is_declaration("oneof") {
+                self.oneof(message)?;
+            } else if self.is_keyword("reserved") && !self.is_field_named_like_keyword() {
+                self.index += 1;
+                let (ranges, names) = self.reserved(MAX_FIELD_NUMBER)?;
+                message.reserved_ranges.extend(ranges);
+                message.reserved_names.extend(names);
+            } else if self.is_keyword("extensions") && !self.is_field_named_like_keyword() {
+                self.index += 1;
+                message
+                    .extension_ranges
+                    .extend(self.ranges(MAX_FIELD_NUMBER)?);
+                self.field_options()?;
+                self.expect_symbol(';')?;
+            } else {
+                self.field(message, None)?;
+            }
+        }
+    }
+
+    /// Declarations start with a keyword followed by a name, which distinguishes them from
+    /// fields whose type happens to share a keyword's name.
+    fn is_declaration(&self, keyword: &str) -> bool {
+        if !self.is_keyword(keyword) {
+            return false;
+        }
+        match (self.peek_ahead(1), self.peek_ahead(2)) {
+            (Some(Token::Ident(_)), Some(Token::Symbol('='))) => false,
+            (Some(Token::Ident(_)), _) => true,
+            (Some(Token::Symbol('.')), _) => keyword == "extend",
+            _ => false,
+        }
+    }
+
+    fn is_field_named_like_keyword(&self) -> bool {
+        matches!(
+            (self.peek_ahead(1), self.peek_ahead(2)),
+            (Some(Token::Ident(_)), Some(Token::Symbol('='))) | (Some(Token::Symbol('.')), _)
+        )
+    }
+
+    fn label(&mut self) -> Label {
+        // A label is only a label if a type follows it, otherwise it is the type itself.
+        let followed_by_type = matches!(
+            self.peek_ahead(1),
+            Some(Token::Ident(_)) | Some(Token::Symbol('.'))
+        );
+        if !followed_by_type {
+            return Label::None;
+        }
+        if self.eat_keyword("optional") {
+            Label::Optional
+        } else if self.eat_keyword("required") {
+            Label::Required
+        } else if self.eat_keyword("repeated") {
+            Label::Repeated
+        } else {
+            Label::None
+        }
+    }
+
+    fn field_type(&mut self) -> ParseResult<String> {
+        if self.is_keyword("map") && self.peek_ahead(1) == Some(&Token::Symbol('<')) {
+            self.index += 2;
+            let key = self.ident()?;
+            self.expect_symbol(',')?;
+            let value = self.full_ident()?;
+            self.expect_symbol('>')?;
+            Ok(format!("map<{}, {}>", key, value))
+        } else {
+            self.full_ident()
+        }
+    }
+
+    fn field(&mut self, message: &mut Message, oneof: Option<&str>) -> ParseResult<()> {
+        let position = self.position();
+        let label = self.label();
+
+        if self.is_keyword("group") && self.peek_ahead(1) != Some(&Token::Symbol('=')) {
+            return self.group(message, label, oneof, position);
+        }
+
+        let type_name = self.field_type()?;
+        let name = self.ident()?;
+        self.expect_symbol('=')?;
+        let number = self.integer()?;
+        let options = self.field_options()?;
+        self.expect_symbol(';')?;
+
+        message.fields.push(Field {
+            name,
+            number,
+            label,
+            type_name,
+            oneof: oneof.map(|s| s.to_owned()),
+            options,
+            position,
+        });
+        Ok(())
+    }
+
+    /// Proto2 groups declare a nested message and a field of that type in one go.
+    fn group(
+        &mut self,
+        message: &mut Message,
+        label: Label,
+        oneof: Option<&str>,
+        position: Position,
+    ) -> ParseResult<()> {
+        self.expect_keyword("group")?;
+        let type_name = self.ident()?;
+        self.expect_symbol('=')?;
+        let number = self.integer()?;
+        let options = self.field_options()?;
+
+        let mut nested = Message {
+            name: type_name.clone(),
+            position,
+            ..Message::default()
+        };
+        self.message_body(&mut nested)?;
+
+        message.fields.push(Field {
+            name: type_name.to_lowercase(),
+            number,
+            label,
+            type_name,
+            oneof: oneof.map(|s| s.to_owned()),
+            options,
+            position,
+        });
+        message.messages.push(nested);
+        Ok(())
+    }
+
+    fn oneof(&mut self, message: &mut Message) -> ParseResult<()> {
+        let position = self.position();
+        self.expect_keyword("oneof")?;
+        let name = self.ident()?;
+        self.expect_symbol('{')?;
+        loop {
+            if self.eat_symbol('}') {
+                break;
+            } else if self.peek().is_none() {
+                return self.error("'}'");
+            } else if self.eat_symbol(';') {
+                continue;
+            } else if self.is_keyword("option") {
+                self.option_statement()?;
+            } else {
+                self.field(message, Some(&name))?;
+            }
+        }
+        message.oneofs.push(Oneof { name, position });
+        Ok(())
+    }
+
+    fn ranges(&mut self, max: i64) -> ParseResult<Vec<Range>> {
+        let mut ranges = Vec::new();
+        loop {
+            let start = self.integer()?;
+            let end = if self.eat_keyword("to") {
+                if self.eat_keyword("max") {
+                    max
+                } else {
+                    self.integer()?
+                }
+            } else {
+                start
+            };
+            ranges.push((start, end));
+            if !self.eat_symbol(',') {
+                return Ok(ranges);
+            }
+        }
+    }
+
+    fn reserved(&mut self, max: i64) -> ParseResult<(Vec<Range>, Vec<String>)> {
+        let mut names = Vec::new();
+        let mut ranges = Vec::new();
+        match self.peek() {
+            Some(Token::Str(_)) | Some(Token::Ident(_)) => loop {
+                names.push(match self.peek() {
+                    Some(Token::Ident(_)) => self.ident()?,
+                    _ => self.string()?,
+                });
+                if !self.eat_symbol(',') {
+                    break;
+                }
+            },
+            _ => ranges = self.ranges(max)?,
+        }
+        self.expect_symbol(';')?;
+        Ok((ranges, names))
+    }
+
+    fn enumeration(&mut self) -> ParseResult<Enum> {
+        let position = self.position();
+        self.expect_keyword("enum")?;
+        let name = self.ident()?;
+        let mut enumeration = Enum {
+            name,
+            position,
+            ..Enum::default()
+        };
+        self.expect_symbol('{')?;
+        loop {
+            let position = self.position();
+            if self.eat_symbol('}') {
+                return Ok(enumeration);
+            } else if self.peek().is_none() {
+                return self.error("'}'");
+            } else if self.eat_symbol(';') {
+                continue;
+            } else if self.is_keyword("option") && self.peek_ahead(1) != Some(&Token::Symbol('=')) {
+                enumeration.options.push(self.option_statement()?);
+            } else if self.is_keyword("reserved") && self.peek_ahead(1) != Some(&Token::Symbol('='))
+            {
+                self.index += 1;
+                let (ranges, names) = self.reserved(MAX_ENUM_NUMBER)?;
+                enumeration.reserved_ranges.extend(ranges);
+                enumeration.reserved_names.extend(names);
+            } else {
+                let name = self.ident()?;
+                self.expect_symbol('=')?;
+                let number = self.integer()?;
+                self.field_options()?;
+                self.expect_symbol(';')?;
+                enumeration.values.push(EnumValue {
+                    name,
+                    number,
+                    position,
+                });
+            }
+        }
+    }
+
+    fn service(&mut self) -> ParseResult<Service> {
+        let position = self.position();
+        self.expect_keyword("service")?;
+        let name = self.ident()?;
+        let mut service = Service {
+            name,
+            position,
+            ..Service::default()
+        };
+        self.expect_symbol('{')?;
+        loop {
+            if self.eat_symbol('}') {
+                return Ok(service);
+            } else if self.peek().is_none() {
+                return self.error("'}'");
+            } else if self.eat_symbol(';') {
+                continue;
+            } else if self.is_keyword("option") {
+                service.options.push(self.option_statement()?);
+            } else if self.is_keyword("rpc") {
+                service.rpcs.push(self.rpc()?);
+            } else {
+                return self.error("'rpc' or 'option'");
+            }
+        }
+    }
+
+    fn rpc(&mut self) -> ParseResult<Rpc> {
+        let position = self.position();
+        self.expect_keyword("rpc")?;
+        let name = self.ident()?;
+        let (client_streaming, input_type) = self.rpc_type()?;
+        self.expect_keyword("returns")?;
+        let (server_streaming, output_type) = self.rpc_type()?;
+
+        if self.eat_symbol('{') {
+            loop {
+                if self.eat_symbol('}') {
+                    break;
+                } else if self.peek().is_none() {
+                    return self.error("'}'");
+                } else if self.eat_symbol(';') {
+                    continue;
+                } else {
+                    self.option_statement()?;
+                }
+            }
+        } else {
+            self.expect_symbol(';')?;
+        }
+
+        Ok(Rpc {
+            name,
+            input_type,
+            output_type,
+            client_streaming,
+            server_streaming,
+            position,
+        })
+    }
+
+    fn rpc_type(&mut self) -> ParseResult<(bool, String)> {
+        self.expect_symbol('(')?;
+        let streaming = match self.peek_ahead(1) {
+            Some(Token::Ident(_)) | Some(Token::Symbol('.')) => self.eat_keyword("stream"),
+            _ => false,
+        };
+        let type_name = self.full_ident()?;
+        self.expect_symbol(')')?;
+        Ok((streaming, type_name))
+    }
+
+    fn extend(&mut self) -> ParseResult<Extend> {
+        let position = self.position();
+        self.expect_keyword("extend")?;
+        let extendee = self.full_ident()?;
+        // Extension fields are collected through a scratch message so groups work unchanged.
+        let mut scratch = Message::default();
+        self.expect_symbol('{')?;
+        loop {
+            if self.eat_symbol('}') {
+                break;
+            } else if self.peek().is_none() {
+                return self.error("'}'");
+            } else if self.eat_symbol(';') {
+                continue;
+            } else {
+                self.field(&mut scratch, None)?;
+            }
+        }
+        Ok(Extend {
+            extendee,
+            fields: scratch.fields,
+            position,
+        })
+    }
+}
+
+fn parse_integer(literal: &str) -> Option<i64> {
+    if literal.starts_with("0x") || literal.starts_with("0X") {
+        i64::from_str_radix(&literal[2..], 16).ok()
+    } else if literal.len() > 1 && literal.starts_with('0') {
+        i64::from_str_radix(&literal[1..], 8).ok()
+    } else {
+        literal.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse;
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+syntax = "proto3";
+
+package skyscanner.protovend.v1;
+
+import "google/protobuf/timestamp.proto";
+import public "skyscanner/common/money.proto";
+
+option java_package = "net.skyscanner.protovend";
+option (custom.file_option) = { key: "value" nested { a: 1 } };
+
+// A heartbeat sent by services.
+message Heartbeat {
+  message Inner {
+    repeated string tags = 1 [packed = true, (custom.field) = "x"];
+  }
+  enum Status {
+    option allow_alias = true;
+    STATUS_UNSPECIFIED = 0;
+    STATUS_OK = 1;
+    STATUS_DEGRADED = -1;
+    reserved 5 to 10, 20;
+    reserved "STATUS_GONE";
+  }
+  string service_name = 1;
+  google.protobuf.Timestamp sent_at = 2;
+  map<string, .skyscanner.common.Money> costs = 3;
+  oneof payload {
+    Inner inner = 4;
+    int64 count = 5;
+  }
+  reserved 6, 8 to max;
+  reserved "old_field";
+  optional bool healthy = 7;
+}
+
+service HeartbeatService {
+  option deprecated = true;
+  rpc Beat (Heartbeat) returns (stream Heartbeat);
+  rpc Stream (stream .skyscanner.protovend.v1.Heartbeat) returns (Heartbeat) {
+    option idempotency_level = NO_SIDE_EFFECTS;
+  }
+}
+"#;
+
+    #[test]
+    fn test_parses_example_file() {
+        let file = parse(EXAMPLE).unwrap();
+
+        assert_eq!(Syntax::Proto3, file.syntax);
+        assert_eq!("skyscanner.protovend.v1", file.package.unwrap().name);
+        assert_eq!(2, file.imports.len());
+        assert_eq!(ImportKind::Public, file.imports[1].kind);
+        assert_eq!(2, file.options.len());
+
+        let message = &file.messages[0];
+        assert_eq!("Heartbeat", message.name);
+        assert_eq!(
+            Position {
+                line: 13,
+                column: 1
+            },
+            message.position
+        );
+        let fields: Vec<(&str, i64)> = message
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.number))
+            .collect();
+        assert_eq!(
+            vec![
+                ("service_name", 1),
+                ("sent_at", 2),
+                ("costs", 3),
+                ("inner", 4),
+                ("count", 5),
+                ("healthy", 7)
+            ],
+            fields
+        );
+        assert_eq!(
+            "map<string, .skyscanner.common.Money>",
+            message.fields[2].type_name
+        );
+        assert_eq!(Some("payload".to_owned()), message.fields[3].oneof);
+        assert_eq!(Label::Optional, message.fields[5].label);
+        assert_eq!(vec![(6, 6), (8, MAX_FIELD_NUMBER)], message.reserved_ranges);
+        assert_eq!(vec!["old_field".to_owned()], message.reserved_names);
+        assert_eq!(Label::Repeated, message.messages[0].fields[0].label);
+
+        let status = &message.enums[0];
+        assert_eq!(3, status.values.len());
+        assert_eq!(-1, status.values[2].number);
+        assert_eq!(vec![(5, 10), (20, 20)], status.reserved_ranges);
+
+        let service = &file.services[0];
+        assert_eq!(2, service.rpcs.len());
+        assert!(service.rpcs[0].server_streaming);
+        assert!(!service.rpcs[0].client_streaming);
+        assert!(service.rpcs[1].client_streaming);
+        assert_eq!(
+            ".skyscanner.protovend.v1.Heartbeat",
+            service.rpcs[1].input_type
+        );
+    }
+
+    #[test]
+    fn test_parses_proto2_groups_and_extensions() {
+        let file = parse(
+            "syntax = \"proto2\";\
+             \nmessage Search {\
+             \n  extensions 100 to 199;\
+             \n  required string query = 1 [default = \"\"];\
+             \n  repeated group Result = 2 { optional string url = 3; }\
+             \n}\
+             \nextend Search { optional int32 page = 100; }",
+        )
+        .unwrap();
+
+        assert_eq!(Syntax::Proto2, file.syntax);
+        assert_eq!(vec![(100, 199)], file.messages[0].extension_ranges);
+        assert_eq!("Result", file.messages[0].fields[1].type_name);
+        assert_eq!("Result", file.messages[0].messages[0].name);
+        assert_eq!("page", file.extends[0].fields[0].name);
+    }
+
+    #[test]
+    fn test_fields_may_be_named_after_keywords() {
+        let file = parse(
+            "syntax = \"proto3\";\
+             \nmessage Foo {\
+             \n  string option = 1;\
+             \n  int32 reserved = 2;\
+             \n  message message = 3;\
+             \n}\
+             \nmessage message {}",
+        )
+        .unwrap();
+
+        let names: Vec<&str> = file.messages[0]
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(vec!["option", "reserved", "message"], names);
+    }
+
+    #[test]
+    fn test_reports_position_of_syntax_error() {
+        let error = parse("syntax = \"proto3\";\nmessage Foo {\n  string name = ;\n}").unwrap_err();
+
+        assert_eq!(
+            Position {
+                line: 3,
+                column: 17
+            },
+            error.position
+        );
+        assert_eq!("expected integer, found ';'", error.message);
+    }
+
+    #[test]
+    fn test_reports_unclosed_message() {
+        let error = parse("message Foo {\n  string name = 1;\n").unwrap_err();
+
+        assert_eq!("expected '}', found end of file", error.message);
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use crate::git_url::GitUrl;
+use chrono::NaiveDateTime;
+use failure::format_err;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// What a command changed, for reporting to tools wrapping protovend.
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub dependencies: Vec<DependencyChange>,
+    pub files_written: Vec<PathBuf>,
+    pub consumers: Vec<Consumer>,
+    pub listed: Vec<ListedDependency>,
+    pub tree: Vec<TreeDependency>,
+    pub traced: Vec<TracedProto>,
+    pub lint_results: Vec<LintResult>,
+    /// Whether `outdated` found dependencies with newer commits, so protovend exits with code 2.
+    #[serde(skip)]
+    pub outdated: bool,
+}
+
+impl Summary {
+    /// Why the command failed despite running to completion, as when lint reports errors.
+    pub fn error(&self) -> Option<failure::Error> {
+        if self
+            .lint_results
+            .iter()
+            .any(|result| result.severity == "error")
+        {
+            Some(format_err!("Validation errors reported"))
+        } else {
+            None
+        }
+    }
+}
+
+/// A dependency added, removed or moved to a new commit. Commits are absent for dependencies
+/// which are not locked before or after the command.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DependencyChange {
+    pub url: GitUrl,
+    pub branch: String,
+    pub old_commit: Option<String>,
+    pub new_commit: Option<String>,
+}
+
+/// A project vendoring a producer's protos, as found by scanning checked out repos.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Consumer {
+    pub producer: GitUrl,
+    pub project: String,
+    pub path: PathBuf,
+    pub branch: String,
+    pub commit: String,
+    /// Dependencies the producer was vendored through, empty if the project declares it.
+    pub via: Vec<GitUrl>,
+    pub locked: NaiveDateTime,
+    /// Only known when the producer's repo was fetched.
+    pub commits_behind: Option<usize>,
+}
+
+/// A dependency as shown by `list`. The commit and file count are absent for dependencies
+/// which are not locked.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ListedDependency {
+    pub url: GitUrl,
+    pub branch: String,
+    pub commit: Option<String>,
+    pub files: Option<usize>,
+    pub status: String,
+}
+
+/// A locked dependency as shown by `tree`, with the files vendored for it.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TreeDependency {
+    pub url: GitUrl,
+    pub branch: String,
+    pub commit: String,
+    pub files: Vec<VendoredFile>,
+}
+
+/// A vendored file, relative to the output directory, with the files it imports.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct VendoredFile {
+    pub path: PathBuf,
+    pub imports: Vec<ImportedFile>,
+}
+
+/// A file named by an import statement and where it comes from: the url of the dependency
+/// providing it, `well known type`, `this project` or `not found`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ImportedFile {
+    pub file: String,
+    pub provider: String,
+}
+
+/// A vendored proto matching a `why` query, with the import it came from and the project's
+/// protos importing it.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TracedProto {
+    pub file: PathBuf,
+    /// The package or name the file matched on, absent when the query named the file.
+    pub matched: Option<String>,
+    pub url: GitUrl,
+    pub branch: String,
+    pub commit: String,
+    /// Dependencies the import was vendored through, empty if the project declares it.
+    pub via: Vec<GitUrl>,
+    pub imported_by: Vec<PathBuf>,
+}
+
+/// A problem reported by `lint`, with the file, relative to the project, it was found in.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LintResult {
+    pub rule: String,
+    /// Either `warning` or `error`.
+    pub severity: String,
+    pub file: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
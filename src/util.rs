@@ -14,7 +14,13 @@
  * limitations under the License.
 */
 
+use crate::Result;
+use chrono::Duration;
+use failure::format_err;
 use semver::Version;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 pub fn is_valid_version(version: &Version) -> bool {
     *crate::CRATE_VERSION >= *version
@@ -23,3 +29,142 @@ pub fn is_valid_version(version: &Version) -> bool {
 pub fn to_alpha_num(s: &str) -> String {
     s.chars().filter(|c| c.is_alphanumeric()).collect()
 }
+
+pub fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Sibling of `path` used to stage a replacement for it, hidden so that it is not mistaken for
+/// real output if a crash leaves it behind.
+pub fn staging_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+/// Writes the file by renaming a fully written sibling over it, so a failure part way through
+/// leaves the previous contents in place.
+pub fn write_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
+    let path = path.as_ref();
+    let staged = write_staged(path, contents)?;
+
+    let result = fs::rename(&staged, path);
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    Ok(result?)
+}
+
+/// Writes the contents the file should have to a sibling, returning its path, for the caller
+/// to rename over the file.
+pub fn write_staged<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<PathBuf> {
+    let staged = staging_path(path.as_ref(), "tmp");
+
+    let result = File::create(&staged).and_then(|mut f| {
+        f.write_all(contents.as_ref())?;
+        f.sync_all()
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result?;
+    Ok(staged)
+}
+
+/// 64-bit FNV-1a hash, stable across platforms and releases unlike the standard library's.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Parses a duration such as `90m`, `12h`, `30d` or `2w`.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format_err!("Invalid duration {}; expected e.g. 12h, 30d or 2w", s))?;
+
+    match unit {
+        "s" => Ok(Duration::seconds(amount)),
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(format_err!(
+            "Invalid duration {}; expected a unit of s, m, h, d or w",
+            s
+        )),
+    }
+}
+
+/// A size in bytes in the largest binary unit keeping it above one, e.g. `3.2 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_file_without_leaving_staged_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".protovend.lock");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, "new").unwrap();
+
+        assert_eq!("new", fs::read_to_string(&path).unwrap());
+        assert!(!staging_path(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_previous_contents_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("existing");
+        fs::create_dir(&path).unwrap();
+
+        assert!(write_atomic(&path, "new").is_err());
+        assert!(path.is_dir());
+        assert!(!staging_path(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(Duration::days(30), parse_duration("30d").unwrap());
+        assert_eq!(Duration::weeks(2), parse_duration("2w").unwrap());
+        assert_eq!(Duration::minutes(90), parse_duration("90m").unwrap());
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!("512 B", format_size(512));
+        assert_eq!("1.5 KiB", format_size(1536));
+        assert_eq!("3.0 MiB", format_size(3 * 1024 * 1024));
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(0xcbf2_9ce4_8422_2325, fnv1a(b""));
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(b"a"));
+        assert_eq!(0x8594_4171_f739_67e8, fnv1a(b"foobar"));
+    }
+}
@@ -14,8 +14,8 @@
  * limitations under the License.
 */
 
-use common::command;
-use git2::Repository;
+use common::{command, command_with_upstream, git};
+use git2::{Repository, Signature};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::Path;
@@ -35,12 +35,49 @@ fn create_with_protos_in_correct_location(path: &Path) {
     file.write_all(b"test").unwrap();
 }
 
+fn create_with_unparsable_proto_in_correct_location(path: &Path) {
+    fs::create_dir_all(path.join("proto/skyscanner/protovend")).unwrap();
+    let mut file = File::create(path.join("proto/skyscanner/protovend/broken.proto")).unwrap();
+    file.write_all(b"syntax = \"proto3\";\nmessage Broken {\n  string name = ;\n}")
+        .unwrap();
+}
+
+fn create_with_package(path: &Path, package: &str) {
+    fs::create_dir_all(path.join("proto/skyscanner/protovend/v1")).unwrap();
+    let mut file = File::create(path.join("proto/skyscanner/protovend/v1/service.proto")).unwrap();
+    write!(file, "syntax = \"proto3\";\npackage {};\n", package).unwrap();
+}
+
+fn write_config(path: &Path, contents: &str) {
+    let mut file = File::create(path.join(".protovend.yml")).unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+}
+
 fn init_git_working_dir(path: &Path) {
     let repo = Repository::init(path).unwrap();
     repo.remote("origin", "git@github.com:Skyscanner/protovend.git")
         .unwrap();
 }
 
+fn commit_all(path: &Path) {
+    let repo = Repository::open(path).unwrap();
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(vec!["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("test", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "test", &tree, &[])
+        .unwrap();
+}
+
+fn write_proto(path: &Path, contents: &str) {
+    fs::create_dir_all(path.join("proto/skyscanner/protovend")).unwrap();
+    let mut file = File::create(path.join("proto/skyscanner/protovend/service.proto")).unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+}
+
 #[test]
 fn test_cli_exits_with_failure_when_checker_fails() {
     let dir = tempfile::tempdir().unwrap();
@@ -62,3 +99,473 @@ fn test_cli_exits_with_pass_when_checker_passes() {
 
     assert!(status.success());
 }
+
+#[test]
+fn test_cli_reports_location_of_unparsable_proto() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_unparsable_proto_in_correct_location(dir.path());
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir).arg("lint").output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("broken.proto:3:17: P003"));
+}
+
+#[test]
+fn test_cli_reports_package_not_matching_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_package(dir.path(), "skyscanner.protovend");
+    write_config(
+        dir.path(),
+        "min_protovend_version: 0.0.0\nvendor: []\nlint:\n  package_layout: directory",
+    );
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir).arg("lint").output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("service.proto:2:1: P004"));
+}
+
+#[test]
+fn test_cli_accepts_package_with_project_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_package(dir.path(), "skyscanner.protovend");
+    write_config(
+        dir.path(),
+        "min_protovend_version: 0.0.0\nvendor: []\nlint:\n  package_layout: prefix",
+    );
+    init_git_working_dir(dir.path());
+
+    let status = command(&dir).arg("lint").status().unwrap();
+
+    assert!(status.success());
+}
+
+#[test]
+fn test_cli_passes_with_rule_downgraded_to_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_package(dir.path(), "skyscanner.protovend");
+    write_config(
+        dir.path(),
+        "min_protovend_version: 0.0.0\nvendor: []\nlint:\n  package_layout: directory\n  rules:\n    P004: warn",
+    );
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir).arg("lint").output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("(WARN)"));
+    assert!(stdout.contains("service.proto:2:1: P004"));
+}
+
+#[test]
+fn test_cli_respects_inline_suppression() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_package(dir.path(), "skyscanner.protovend");
+    let mut file = File::create(
+        dir.path()
+            .join("proto/skyscanner/protovend/v1/service.proto"),
+    )
+    .unwrap();
+    file.write_all(
+        b"syntax = \"proto3\";\n// protovend:ignore P004\npackage skyscanner.protovend;\n",
+    )
+    .unwrap();
+    write_config(
+        dir.path(),
+        "min_protovend_version: 0.0.0\nvendor: []\nlint:\n  package_layout: directory",
+    );
+    init_git_working_dir(dir.path());
+
+    let status = command(&dir).arg("lint").status().unwrap();
+
+    assert!(status.success());
+}
+
+#[test]
+fn test_cli_fails_on_style_rule_promoted_to_error() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_package(dir.path(), "skyscanner.protovend");
+    write_config(
+        dir.path(),
+        "min_protovend_version: 0.0.0\nvendor: []\nlint:\n  rules:\n    P011: error",
+    );
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir).arg("lint").output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("(ERROR)"));
+    assert!(stdout.contains(
+        "service.proto:2:1: P011 Package skyscanner.protovend should end with a version"
+    ));
+}
+
+#[test]
+fn test_cli_fails_on_breaking_change_against_ref() {
+    let dir = tempfile::tempdir().unwrap();
+    init_git_working_dir(dir.path());
+    write_proto(
+        dir.path(),
+        "syntax = \"proto3\";\nmessage Foo {\n  string name = 1;\n  int32 age = 2;\n}",
+    );
+    commit_all(dir.path());
+    write_proto(
+        dir.path(),
+        "syntax = \"proto3\";\nmessage Foo {\n  string name = 1;\n}",
+    );
+
+    let output = command(&dir)
+        .arg("lint")
+        .arg("--breaking-against=HEAD")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("P005 message Foo is not backward compatible with HEAD"));
+}
+
+#[test]
+fn test_cli_passes_on_compatible_change_against_ref() {
+    let dir = tempfile::tempdir().unwrap();
+    init_git_working_dir(dir.path());
+    write_proto(
+        dir.path(),
+        "syntax = \"proto3\";\nmessage Foo {\n  string name = 1;\n}",
+    );
+    commit_all(dir.path());
+    write_proto(
+        dir.path(),
+        "syntax = \"proto3\";\nmessage Foo {\n  string name = 1;\n  int32 age = 2;\n}",
+    );
+
+    let status = command(&dir)
+        .arg("lint")
+        .arg("--breaking-against=HEAD")
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+}
+
+#[test]
+fn test_cli_reports_json_results_on_stdout() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_unparsable_proto_in_correct_location(dir.path());
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir)
+        .arg("lint")
+        .arg("--format=json")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let result = &report["results"][0];
+    assert_eq!("P003", result["rule"]);
+    assert_eq!("error", result["severity"]);
+    assert_eq!(3, result["line"]);
+    assert!(result["file"].as_str().unwrap().ends_with("broken.proto"));
+}
+
+#[test]
+fn test_cli_reports_github_annotations() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_protos_in_root(dir.path());
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir)
+        .arg("lint")
+        .arg("--format=github")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout
+        .lines()
+        .any(|line| line.starts_with("::error file=proto,title=P001::")));
+}
+
+fn create_with_misplaced_protos(path: &Path) {
+    fs::create_dir_all(path.join("proto/common")).unwrap();
+    fs::write(
+        path.join("proto/common/types.proto"),
+        "syntax = \"proto3\";\nmessage Money {}\n",
+    )
+    .unwrap();
+    fs::write(
+        path.join("proto/service.proto"),
+        "syntax = \"proto3\";\nimport \"common/types.proto\";\nmessage Price {\n  Money amount = 1;\n}\n",
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_cli_fix_relocates_protos_and_rewrites_imports() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_misplaced_protos(dir.path());
+    init_git_working_dir(dir.path());
+
+    let status = command(&dir).arg("lint").arg("--fix").status().unwrap();
+
+    assert!(status.success());
+    let moved = dir.path().join("proto/skyscanner/protovend");
+    assert!(moved.join("common/types.proto").is_file());
+    assert!(!dir.path().join("proto/common").exists());
+    let service = fs::read_to_string(moved.join("service.proto")).unwrap();
+    assert!(service.contains("import \"skyscanner/protovend/common/types.proto\";"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_fix_keeps_symlinks_and_empty_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_misplaced_protos(dir.path());
+    fs::create_dir_all(dir.path().join("proto/empty")).unwrap();
+    std::os::unix::fs::symlink("../README.md", dir.path().join("proto/README.md")).unwrap();
+    init_git_working_dir(dir.path());
+
+    let status = command(&dir).arg("lint").arg("--fix").status().unwrap();
+
+    assert!(status.success());
+    assert!(dir.path().join("proto/empty").is_dir());
+    assert_eq!(
+        Path::new("../README.md"),
+        fs::read_link(dir.path().join("proto/README.md")).unwrap()
+    );
+    assert!(dir
+        .path()
+        .join("proto/skyscanner/protovend/service.proto")
+        .is_file());
+}
+
+#[test]
+fn test_cli_fix_dry_run_leaves_protos_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_misplaced_protos(dir.path());
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir)
+        .arg("lint")
+        .arg("--fix")
+        .arg("--dry-run")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Would move"));
+    assert!(stdout.contains("Would rewrite 1 import(s)"));
+    assert!(dir.path().join("proto/service.proto").is_file());
+}
+
+#[test]
+fn test_cli_checks_vendored_protos_against_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    write_config(
+        dir.path(),
+        "min_protovend_version: 0.0.0\nvendor:\n  - url: git@github.com:org/producer.git\n    branch: master",
+    );
+    fs::create_dir_all(dir.path().join("vendor/proto/org/producer")).unwrap();
+    fs::write(
+        dir.path().join("vendor/proto/org/producer/added.proto"),
+        "syntax = \"proto3\";",
+    )
+    .unwrap();
+    fs::write(dir.path().join("vendor/proto/.gitkeep"), "").unwrap();
+    fs::write(dir.path().join("vendor/proto/org/README.md"), "# Vendored").unwrap();
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir).arg("lint").output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains(".gitkeep"));
+    assert!(!stdout.contains("README.md"));
+    assert!(stdout.contains(
+        ".protovend.lock: P015 git@github.com:org/producer.git (master branch) is not locked"
+    ));
+    assert!(stdout.contains("added.proto: P013"));
+    assert!(!stdout.contains("P002"));
+}
+
+#[test]
+fn test_cli_checks_vendored_protos_from_the_cache_without_fetching() {
+    let upstream = tempfile::tempdir().unwrap();
+    fs::create_dir_all(upstream.path().join("proto/org/producer")).unwrap();
+    fs::write(
+        upstream.path().join("proto/org/producer/a.proto"),
+        "syntax = \"proto3\";",
+    )
+    .unwrap();
+    git(&upstream, &["init", "-b", "master"]);
+    git(&upstream, &["add", "."]);
+    git(&upstream, &["commit", "-m", "first"]);
+
+    let url = "https://example.invalid/org/producer.git";
+    let dir = tempfile::tempdir().unwrap();
+    write_config(
+        dir.path(),
+        &format!(
+            "min_protovend_version: 0.0.0\nvendor:\n  - url: {}\n    branch: master",
+            url
+        ),
+    );
+    init_git_working_dir(dir.path());
+    let cache = tempfile::tempdir().unwrap();
+    let status = command_with_upstream(&dir, url, upstream.path())
+        .env("TMPDIR", cache.path())
+        .arg("install")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    fs::write(
+        dir.path().join("vendor/proto/org/producer/a.proto"),
+        "syntax = \"proto2\";",
+    )
+    .unwrap();
+
+    let output = command(&dir)
+        .env("TMPDIR", cache.path())
+        .arg("lint")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("a.proto: P014"));
+    assert!(!stdout.contains("P016"));
+
+    let empty_cache = tempfile::tempdir().unwrap();
+    let output = command(&dir)
+        .env("TMPDIR", empty_cache.path())
+        .arg("lint")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("P016"));
+    assert!(stdout.contains("run protovend install to fetch it"));
+    assert!(!stdout.contains("a.proto: P01"));
+}
+
+#[test]
+fn test_cli_uses_url_option_without_git_remote() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_package(dir.path(), "skyscanner.protovend.v1");
+    fs::write(
+        dir.path().join("proto/misplaced.proto"),
+        "syntax = \"proto3\";",
+    )
+    .unwrap();
+    Repository::init(dir.path()).unwrap();
+
+    let output = command(&dir)
+        .arg("lint")
+        .arg("--url=git@github.com:Skyscanner/protovend.git")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("Unable to find the project's git url"));
+    assert!(stdout.contains("P001"));
+    assert!(stdout.contains("they should be moved to skyscanner/protovend"));
+}
+
+#[test]
+fn test_cli_uses_project_url_from_config() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_protos_in_root(dir.path());
+    write_config(
+        dir.path(),
+        "min_protovend_version: 0.0.0\nproject_url: git@github.com:Skyscanner/protovend.git\nvendor: []",
+    );
+
+    let output = command(&dir).arg("lint").output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("they should be moved to skyscanner/protovend"));
+}
+
+#[test]
+fn test_cli_tries_remotes_other_than_origin() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_package(dir.path(), "skyscanner.protovend.v1");
+    fs::write(
+        dir.path().join("proto/misplaced.proto"),
+        "syntax = \"proto3\";",
+    )
+    .unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    repo.remote("upstream", "git@github.com:Skyscanner/protovend.git")
+        .unwrap();
+
+    let output = command(&dir).arg("lint").output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("Unable to find the project's git url"));
+    assert!(stdout.contains("P001"));
+    assert!(stdout.contains("they should be moved to skyscanner/protovend"));
+}
+
+#[test]
+fn test_cli_runs_generic_checks_without_project_url() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_unparsable_proto_in_correct_location(dir.path());
+
+    let output = command(&dir).arg("lint").output().unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Unable to find the project's git url"));
+    assert!(stdout.contains("broken.proto:3:17: P003"));
+    assert!(!stdout.contains("P002"));
+}
+
+#[test]
+fn test_cli_reports_lint_errors_in_json_output() {
+    let dir = tempfile::tempdir().unwrap();
+    create_with_unparsable_proto_in_correct_location(dir.path());
+    init_git_working_dir(dir.path());
+
+    let output = command(&dir)
+        .arg("--output=json")
+        .arg("lint")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(false, result["success"]);
+    let errors = result["errors"].as_array().unwrap();
+    assert!(errors[0]
+        .as_str()
+        .unwrap()
+        .contains("broken.proto:3:17: P003"));
+    assert_eq!(
+        "Exiting early: Validation errors reported",
+        errors.last().unwrap()
+    );
+    let lint_results = result["lint_results"].as_array().unwrap();
+    assert_eq!(1, lint_results.len());
+    assert_eq!("P003", lint_results[0]["rule"]);
+    assert_eq!("error", lint_results[0]["severity"]);
+    assert!(lint_results[0]["file"]
+        .as_str()
+        .unwrap()
+        .ends_with("broken.proto"));
+    assert_eq!(3, lint_results[0]["line"]);
+}
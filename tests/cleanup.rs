@@ -0,0 +1,203 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use common::command;
+use fs2::FileExt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tempfile;
+
+mod common;
+
+fn cached_clone(tmp: &Path, path: &str, size: usize) -> PathBuf {
+    let clone = tmp.join(".protovend/repos").join(path);
+    fs::create_dir_all(clone.join(".git")).unwrap();
+    fs::write(clone.join(".git/pack"), vec![0; size]).unwrap();
+    clone
+}
+
+#[test]
+fn test_cleanup_removes_every_cached_repo() {
+    let dir = tempfile::tempdir().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let first = cached_clone(tmp.path(), "githubcom/org/first", 10);
+    let second = cached_clone(tmp.path(), "githubcom/org/second", 10);
+
+    let output = command(&dir)
+        .env("TMPDIR", tmp.path())
+        .arg("cleanup")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Freed 20 B from 2 repo(s); 0 repo(s) using 0 B kept"));
+    assert!(!first.exists());
+    assert!(!second.exists());
+}
+
+#[test]
+fn test_cleanup_removes_interrupted_clones_but_keeps_lock_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let repos = tmp.path().join(".protovend/repos/githubcom/org");
+    let clone = cached_clone(tmp.path(), "githubcom/org/first", 10);
+    fs::write(repos.join(".first.lock"), "").unwrap();
+    // Interrupted before git created the repository
+    fs::create_dir_all(repos.join("second/proto")).unwrap();
+    fs::write(repos.join("second/proto/a.proto"), "").unwrap();
+    fs::write(repos.join(".second.lock"), "").unwrap();
+
+    let output = command(&dir)
+        .env("TMPDIR", tmp.path())
+        .arg("cleanup")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Removed 1 leftover file(s) using 0 B"));
+    assert!(!clone.exists());
+    assert!(!repos.join("second").exists());
+    assert!(repos.join(".first.lock").is_file());
+    assert!(repos.join(".second.lock").is_file());
+}
+
+#[test]
+fn test_cleanup_keeps_locks_held_by_other_processes() {
+    let dir = tempfile::tempdir().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let clone = cached_clone(tmp.path(), "githubcom/org/first", 10);
+    let lock_path = tmp
+        .path()
+        .join(".protovend/repos/githubcom/org/.first.lock");
+    let held = File::create(&lock_path).unwrap();
+    held.try_lock_exclusive().unwrap();
+
+    let output = command(&dir)
+        .env("TMPDIR", tmp.path())
+        .arg("cleanup")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("Skipping githubcom/org/first as another protovend process is using it")
+    );
+    assert!(clone.exists());
+    // A process locking the clone after cleanup still has to wait for the holder
+    let waiting = File::open(&lock_path).unwrap();
+    assert!(waiting.try_lock_exclusive().is_err());
+    drop(held);
+    assert!(waiting.try_lock_exclusive().is_ok());
+}
+
+#[test]
+fn test_cleanup_keeps_lock_files_of_removed_clones() {
+    let dir = tempfile::tempdir().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let clone = cached_clone(tmp.path(), "githubcom/org/first", 10);
+    let lock_path = tmp
+        .path()
+        .join(".protovend/repos/githubcom/org/.first.lock");
+    // Opened by a process waiting on the lock while cleanup holds it
+    let waiting = File::create(&lock_path).unwrap();
+
+    let output = command(&dir)
+        .env("TMPDIR", tmp.path())
+        .arg("cleanup")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(!clone.exists());
+    waiting.try_lock_exclusive().unwrap();
+    let other = File::open(&lock_path).unwrap();
+    assert!(other.try_lock_exclusive().is_err());
+}
+
+#[test]
+fn test_cleanup_unused_keeps_repos_locked_by_project() {
+    let dir = tempfile::tempdir().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let producer = cached_clone(tmp.path(), "githubcom/org/producer-6ac87e2e8a9ff47e", 10);
+    // Cached before clone paths included a hash of the url
+    let legacy = cached_clone(tmp.path(), "githubcom/org/producer", 10);
+    fs::write(
+        dir.path().join(".protovend.lock"),
+        "imports:
+  - branch: master
+    commit: 8b3f1a2c4d5e6f708192a3b4c5d6e7f809102030
+    url: git@github.com:org/producer.git
+min_protovend_version: 0.0.0
+updated: \"2019-11-20 15:02:12.330896\"
+",
+    )
+    .unwrap();
+
+    let output = command(&dir)
+        .env("TMPDIR", tmp.path())
+        .arg("cleanup")
+        .arg("--unused")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Removed githubcom/org/producer (10 B)"));
+    assert!(producer.exists());
+    assert!(!legacy.exists());
+}
+
+#[test]
+fn test_cleanup_dry_run_reports_sizes() {
+    let dir = tempfile::tempdir().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let clone = cached_clone(tmp.path(), "githubcom/org/producer", 2048);
+
+    let output = command(&dir)
+        .env("TMPDIR", tmp.path())
+        .arg("cleanup")
+        .arg("--dry-run")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Would remove githubcom/org/producer (2.0 KiB)"));
+    assert!(stdout.contains("Would free 2.0 KiB from 1 repo(s)"));
+    assert!(clone.exists());
+}
+
+#[test]
+fn test_cleanup_stale_keeps_recently_used_repos() {
+    let dir = tempfile::tempdir().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let clone = cached_clone(tmp.path(), "githubcom/org/producer", 10);
+
+    let output = command(&dir)
+        .env("TMPDIR", tmp.path())
+        .arg("cleanup")
+        .arg("--stale=1d")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Freed 0 B from 0 repo(s); 1 repo(s) using 10 B kept"));
+    assert!(clone.exists());
+}
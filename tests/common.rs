@@ -26,3 +26,41 @@ pub fn command<P: AsRef<Path>>(dir: P) -> Command {
         .env("TMPDIR", tempfile::tempdir().unwrap().path());
     cmd
 }
+
+/// Runs git in `dir`, returning its trimmed output.
+#[allow(dead_code)]
+pub fn git<P: AsRef<Path>>(dir: P, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(&[
+            "-c",
+            "user.name=protovend",
+            "-c",
+            "user.email=protovend@example.com",
+        ])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap().trim().to_owned()
+}
+
+/// Commits an empty change to the current branch of the repo at `dir`, returning its hash.
+#[allow(dead_code)]
+pub fn commit<P: AsRef<Path>>(dir: P, message: &str) -> String {
+    git(&dir, &["commit", "--allow-empty", "-m", message]);
+    git(&dir, &["rev-parse", "HEAD"])
+}
+
+/// A command fetching `url` from the local repo at `upstream` instead.
+#[allow(dead_code)]
+pub fn command_with_upstream<P: AsRef<Path>>(dir: P, url: &str, upstream: &Path) -> Command {
+    let mut cmd = command(dir);
+    cmd.env("GIT_CONFIG_COUNT", "1")
+        .env(
+            "GIT_CONFIG_KEY_0",
+            format!("url.file://{}.insteadOf", upstream.display()),
+        )
+        .env("GIT_CONFIG_VALUE_0", url);
+    cmd
+}
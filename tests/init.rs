@@ -58,3 +58,43 @@ fn test_init_does_not_overwrite() {
         foobar_protovend_path.as_path(),
     )
 }
+
+#[test]
+fn test_init_reports_json_output() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = command(&dir)
+        .arg("--output=json")
+        .arg("init")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!("init", result["command"]);
+    assert_eq!(true, result["success"]);
+    assert_eq!(
+        serde_json::json!([".protovend.yml", ".protovend.lock"]),
+        result["files_written"]
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("(INFO) Created .protovend.yml"));
+}
+
+#[test]
+fn test_init_reports_warnings_in_json_output() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(".protovend.yml"), "foobar").unwrap();
+
+    let output = command(&dir)
+        .arg("--output=json")
+        .arg("init")
+        .output()
+        .unwrap();
+
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        serde_json::json!([".protovend.yml file already exists in project"]),
+        result["warnings"]
+    );
+}
@@ -14,7 +14,7 @@
  * limitations under the License.
 */
 
-use common::command;
+use common::{command, command_with_upstream, git};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use tempfile;
@@ -307,3 +307,86 @@ fn test_install_switch_branch() {
         .join("./vendor/proto/skyscanner/protovendtestprotos/heartbeat-v2.proto")
         .exists());
 }
+
+#[test]
+fn test_install_failure_keeps_vendored_protos() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let status = command(&dir).arg("init").status().unwrap();
+    assert!(status.success());
+    let lock = fs::read_to_string(dir.path().join(".protovend.lock")).unwrap();
+    fs::create_dir_all(dir.path().join("vendor/proto/org/producer")).unwrap();
+    fs::write(
+        dir.path().join("vendor/proto/org/producer/a.proto"),
+        "syntax = \"proto3\";",
+    )
+    .unwrap();
+
+    let status = command(&dir)
+        .arg("add")
+        .arg("https://github.com/Skyscanner/no-such-repo.git")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = command(&dir).arg("install").status().unwrap();
+
+    assert!(!status.success());
+    assert_eq!(
+        lock,
+        fs::read_to_string(dir.path().join(".protovend.lock")).unwrap()
+    );
+    assert!(dir
+        .path()
+        .join("vendor/proto/org/producer/a.proto")
+        .is_file());
+    assert!(!dir.path().join("vendor/.proto.tmp").exists());
+}
+
+#[test]
+fn test_install_producer_depending_on_itself_reports_a_cycle() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = "https://example.com/org/producer.git";
+
+    let upstream = dir.path().join("upstream");
+    fs::create_dir_all(&upstream).unwrap();
+    git(&upstream, &["init", "-b", "master"]);
+    fs::write(
+        upstream.join(".protovend.yml"),
+        format!(
+            "min_protovend_version: 0.0.0\nvendor:\n  - url: {}\n    branch: master\n",
+            url
+        ),
+    )
+    .unwrap();
+    git(&upstream, &["add", "."]);
+    git(&upstream, &["commit", "-m", "depend on itself"]);
+
+    let project = dir.path().join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(
+        project.join(".protovend.yml"),
+        format!(
+            "min_protovend_version: 0.0.0\nvendor:\n  - url: {}\n    branch: master\n    transitive: true\n",
+            url
+        ),
+    )
+    .unwrap();
+
+    let cache = tempfile::tempdir().unwrap();
+    let output = command_with_upstream(&project, url, &upstream)
+        .env("TMPDIR", cache.path())
+        .env("PROTOVEND_LOCK_TIMEOUT", "5")
+        .arg("install")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stdout.contains("Timed out") && !stderr.contains("Timed out"));
+    assert!(
+        stdout.contains("Dependency cycle detected")
+            || stderr.contains("Dependency cycle detected")
+    );
+}
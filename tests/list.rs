@@ -0,0 +1,336 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use common::command;
+use std::fs;
+use std::path::Path;
+use tempfile;
+
+mod common;
+
+const CONFIG: &str = "min_protovend_version: 0.0.0
+vendor:
+  - url: git@github.com:org/producer.git
+    branch: master
+  - url: git@github.com:org/unlocked.git
+    branch: master
+";
+
+const LOCK: &str = "imports:
+  - branch: master
+    commit: 8b3f1a2c4d5e6f708192a3b4c5d6e7f809102030
+    url: git@github.com:org/producer.git
+  - branch: master
+    commit: 1234567c4d5e6f708192a3b4c5d6e7f809102030
+    url: git@github.com:org/types.git
+    via:
+      - git@github.com:org/producer.git
+min_protovend_version: 0.0.0
+updated: \"2019-11-20 15:02:12.330896\"
+";
+
+fn write_project(dir: &Path) {
+    fs::write(dir.join(".protovend.yml"), CONFIG).unwrap();
+    fs::write(dir.join(".protovend.lock"), LOCK).unwrap();
+
+    fs::create_dir_all(dir.join("vendor/proto/org/producer")).unwrap();
+    fs::write(
+        dir.join("vendor/proto/org/producer/service.proto"),
+        "syntax = \"proto3\";\npackage org.producer.v1;\nimport \"org/types/money.proto\";\nimport \"google/protobuf/empty.proto\";\nmessage Order {}",
+    )
+    .unwrap();
+    fs::create_dir_all(dir.join("vendor/proto/org/types")).unwrap();
+    fs::write(
+        dir.join("vendor/proto/org/types/money.proto"),
+        "syntax = \"proto3\";",
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_list_shows_locked_and_unlocked_dependencies() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+
+    let output = command(&dir).arg("list").output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Lock updated 2019-11-20 15:02:12"));
+    assert!(
+        stdout.contains("git@github.com:org/producer.git  master  8b3f1a2    1 file(s)  in sync")
+    );
+    assert!(stdout
+        .contains("git@github.com:org/unlocked.git  master  -------    - file(s)  not locked"));
+    assert!(stdout.contains(
+        "git@github.com:org/types.git     master  1234567    1 file(s)  via git@github.com:org/producer.git"
+    ));
+}
+
+#[test]
+fn test_list_json_output_includes_listed_dependencies() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+
+    let output = command(&dir)
+        .arg("--output=json")
+        .arg("list")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let listed = result["listed"].as_array().unwrap();
+    assert_eq!(3, listed.len());
+    assert_eq!("git@github.com:org/producer.git", listed[0]["url"]);
+    assert_eq!(
+        "8b3f1a2c4d5e6f708192a3b4c5d6e7f809102030",
+        listed[0]["commit"]
+    );
+    assert_eq!(1, listed[0]["files"]);
+    assert_eq!("in sync", listed[0]["status"]);
+    assert_eq!(serde_json::Value::Null, listed[1]["commit"]);
+    assert_eq!("not locked", listed[1]["status"]);
+}
+
+#[test]
+fn test_tree_shows_vendored_files_and_their_imports() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+
+    let output = command(&dir).arg("tree").output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("git@github.com:org/producer.git (master @ 8b3f1a2)"));
+    assert!(stdout.contains("└── org/producer/service.proto"));
+    assert!(stdout.contains("    ├── org/types/money.proto (git@github.com:org/types.git)"));
+    assert!(stdout.contains("    └── google/protobuf/empty.proto (well known type)"));
+}
+
+#[test]
+fn test_tree_json_output_includes_vendored_files() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+
+    let output = command(&dir)
+        .arg("--output=json")
+        .arg("tree")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let tree = result["tree"].as_array().unwrap();
+    assert_eq!(2, tree.len());
+    assert_eq!("git@github.com:org/producer.git", tree[0]["url"]);
+    assert_eq!(
+        "8b3f1a2c4d5e6f708192a3b4c5d6e7f809102030",
+        tree[0]["commit"]
+    );
+    assert_eq!(
+        serde_json::json!([{
+            "path": "org/producer/service.proto",
+            "imports": [
+                {"file": "org/types/money.proto", "provider": "git@github.com:org/types.git"},
+                {"file": "google/protobuf/empty.proto", "provider": "well known type"}
+            ]
+        }]),
+        tree[0]["files"]
+    );
+    assert_eq!("git@github.com:org/types.git", tree[1]["url"]);
+}
+
+#[test]
+fn test_why_traces_vendored_file_to_its_import() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+    fs::create_dir_all(dir.path().join("proto/org/consumer")).unwrap();
+    fs::write(
+        dir.path().join("proto/org/consumer/app.proto"),
+        "syntax = \"proto3\";\nimport \"org/types/money.proto\";",
+    )
+    .unwrap();
+
+    let output = command(&dir)
+        .arg("why")
+        .arg("vendor/proto/org/types/money.proto")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(
+        "vendor/proto/org/types/money.proto is vendored from git@github.com:org/types.git (master branch) at 1234567c4d5e6f708192a3b4c5d6e7f809102030, source proto/org/types/money.proto"
+    ));
+    assert!(stdout.contains("pulled in transitively via git@github.com:org/producer.git"));
+    assert!(stdout.contains("imported by proto/org/consumer/app.proto"));
+}
+
+#[test]
+fn test_why_json_output_includes_the_path_to_the_import() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+
+    let output = command(&dir)
+        .arg("--output=json")
+        .arg("why")
+        .arg("org.producer.v1")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let traced = result["traced"].as_array().unwrap();
+    assert_eq!(1, traced.len());
+    assert_eq!("vendor/proto/org/producer/service.proto", traced[0]["file"]);
+    assert_eq!("package org.producer.v1", traced[0]["matched"]);
+    assert_eq!("git@github.com:org/producer.git", traced[0]["url"]);
+    assert_eq!(serde_json::json!([]), traced[0]["via"]);
+
+    let output = command(&dir)
+        .arg("--output=json")
+        .arg("why")
+        .arg("vendor/proto/org/types/money.proto")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let traced = &result["traced"][0];
+    assert_eq!(serde_json::Value::Null, traced["matched"]);
+    assert_eq!("1234567c4d5e6f708192a3b4c5d6e7f809102030", traced["commit"]);
+    assert_eq!(
+        serde_json::json!(["git@github.com:org/producer.git"]),
+        traced["via"]
+    );
+}
+
+#[test]
+fn test_why_finds_message_by_fully_qualified_name() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+
+    let output = command(&dir)
+        .arg("why")
+        .arg("org.producer.v1.Order")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(
+        "vendor/proto/org/producer/service.proto (defines org.producer.v1.Order) is vendored from git@github.com:org/producer.git"
+    ));
+    assert!(stdout.contains("not imported by any of this project's protos"));
+}
+
+#[test]
+fn test_why_fails_for_unknown_package() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+
+    let output = command(&dir)
+        .arg("why")
+        .arg("org.missing.v1")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("No vendored proto matches org.missing.v1"));
+}
+
+#[test]
+fn test_graph_combines_projects_as_dot() {
+    let first = tempfile::tempdir().unwrap();
+    write_project(first.path());
+    fs::create_dir_all(first.path().join("proto/org/consumer")).unwrap();
+    fs::write(
+        first.path().join("proto/org/consumer/app.proto"),
+        "syntax = \"proto3\";\nimport \"org/producer/service.proto\";",
+    )
+    .unwrap();
+    let second = tempfile::tempdir().unwrap();
+    fs::write(
+        second.path().join(".protovend.yml"),
+        "min_protovend_version: 0.0.0\nproject_url: git@github.com:org/second.git\nvendor:\n  - url: git@github.com:org/types.git\n    branch: master\n",
+    )
+    .unwrap();
+    fs::write(
+        second.path().join(".protovend.lock"),
+        "imports: []\nmin_protovend_version: 0.0.0\nupdated: \"2019-11-20 15:02:12.330896\"\n",
+    )
+    .unwrap();
+
+    let output = command(&first)
+        .arg("graph")
+        .arg(".")
+        .arg(second.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let name = first.path().file_name().unwrap().to_string_lossy();
+    assert!(stdout.starts_with("digraph protovend {"));
+    assert!(stdout.contains(&format!("\"{}\" [shape=box];", name)));
+    assert!(stdout.contains("\"git@github.com:org/second.git\" [shape=box];"));
+    assert!(stdout.contains(&format!(
+        "\"{}\" -> \"git@github.com:org/producer.git\" [style=solid];",
+        name
+    )));
+    assert!(stdout.contains(&format!(
+        "\"{}\" -> \"git@github.com:org/producer.git\" [style=dotted];",
+        name
+    )));
+    assert!(stdout.contains(
+        "\"git@github.com:org/producer.git\" -> \"git@github.com:org/types.git\" [style=dashed];"
+    ));
+    assert!(stdout.contains(
+        "\"git@github.com:org/producer.git\" -> \"git@github.com:org/types.git\" [style=dotted];"
+    ));
+    assert!(stdout.contains(
+        "\"git@github.com:org/second.git\" -> \"git@github.com:org/types.git\" [style=solid];"
+    ));
+}
+
+#[test]
+fn test_graph_as_json() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+
+    let output = command(&dir)
+        .arg("graph")
+        .arg("--format=json")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let graph: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(graph["nodes"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({"id": "git@github.com:org/types.git", "kind": "repo"})));
+    assert!(graph["edges"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({
+            "from": "git@github.com:org/producer.git",
+            "to": "git@github.com:org/types.git",
+            "kind": "transitive"
+        })));
+}
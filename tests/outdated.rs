@@ -0,0 +1,79 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use common::{command, command_with_upstream, commit, git};
+use std::fs;
+use tempfile;
+
+mod common;
+
+#[test]
+fn test_outdated_with_nothing_locked() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let status = command(&dir).arg("init").status().unwrap();
+    assert!(status.success());
+
+    let output = command(&dir).arg("outdated").output().unwrap();
+
+    assert_eq!(Some(0), output.status.code());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("All dependencies are up to date"));
+}
+
+#[test]
+fn test_outdated_exits_with_code_2_when_upstream_is_ahead() {
+    let dir = tempfile::tempdir().unwrap();
+    let upstream = dir.path().join("upstream");
+    fs::create_dir_all(&upstream).unwrap();
+    git(&upstream, &["init", "-b", "master"]);
+    let locked = commit(&upstream, "first");
+    let latest = commit(&upstream, "second");
+
+    let url = "https://example.com/org/producer.git";
+    fs::write(
+        dir.path().join(".protovend.yml"),
+        format!(
+            "min_protovend_version: 0.0.0\nvendor:\n  - url: {}\n    branch: master\n",
+            url
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join(".protovend.lock"),
+        format!(
+            "imports:\n  - branch: master\n    commit: {}\n    url: {}\nmin_protovend_version: 0.0.0\nupdated: \"2019-11-20 15:02:12.330896\"\n",
+            locked, url
+        ),
+    )
+    .unwrap();
+
+    let cache = tempfile::tempdir().unwrap();
+    let output = command_with_upstream(&dir, url, &upstream)
+        .env("TMPDIR", cache.path())
+        .arg("--output=json")
+        .arg("outdated")
+        .output()
+        .unwrap();
+
+    assert_eq!(Some(2), output.status.code());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(true, result["success"]);
+    let dependencies = result["dependencies"].as_array().unwrap();
+    assert_eq!(1, dependencies.len());
+    assert_eq!(locked.as_str(), dependencies[0]["old_commit"]);
+    assert_eq!(latest.as_str(), dependencies[0]["new_commit"]);
+}
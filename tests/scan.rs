@@ -0,0 +1,154 @@
+/*
+ * Copyright 2020 Skyscanner Limited.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+use common::{command, command_with_upstream, commit, git};
+use std::fs;
+use std::path::Path;
+use tempfile;
+
+mod common;
+
+fn write_project(dir: &Path, config: &str, lock: &str) {
+    fs::create_dir_all(dir).unwrap();
+    fs::write(dir.join(".protovend.yml"), config).unwrap();
+    fs::write(dir.join(".protovend.lock"), lock).unwrap();
+}
+
+fn write_projects(root: &Path) {
+    write_project(
+        &root.join("orders"),
+        "min_protovend_version: 0.0.0\nproject_url: git@github.com:org/orders.git\nvendor:\n  - url: git@github.com:org/producer.git\n    branch: master\n",
+        "imports:
+  - branch: master
+    commit: 8b3f1a2c4d5e6f708192a3b4c5d6e7f809102030
+    url: git@github.com:org/producer.git
+  - branch: master
+    commit: 1234567c4d5e6f708192a3b4c5d6e7f809102030
+    url: git@github.com:org/types.git
+    via:
+      - git@github.com:org/producer.git
+min_protovend_version: 0.0.0
+updated: \"2019-11-20 15:02:12.330896\"
+",
+    );
+    write_project(
+        &root.join("nested/payments"),
+        "min_protovend_version: 0.0.0\nvendor:\n  - url: git@github.com:org/types.git\n    branch: master\n",
+        "imports:
+  - branch: master
+    commit: abcdef0c4d5e6f708192a3b4c5d6e7f809102030
+    url: git@github.com:org/types.git
+min_protovend_version: 0.0.0
+updated: \"2019-11-20 15:02:12.330896\"
+",
+    );
+    // Vendored and hidden directories are not searched
+    write_project(
+        &root.join("orders/vendor/copy"),
+        "min_protovend_version: 0.0.0\nvendor: []\n",
+        "imports:\n  - branch: master\n    commit: ffffff0\n    url: git@github.com:org/ignored.git\nmin_protovend_version: 0.0.0\nupdated: \"2019-11-20 15:02:12.330896\"\n",
+    );
+}
+
+#[test]
+fn test_scan_reports_consumers_per_producer() {
+    let dir = tempfile::tempdir().unwrap();
+    write_projects(dir.path());
+
+    let output = command(&dir).arg("scan").arg(".").output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("git@github.com:org/producer.git is vendored by 1 project(s)"));
+    assert!(stdout.contains("git@github.com:org/types.git is vendored by 2 project(s)"));
+    assert!(stdout.contains("  git@github.com:org/orders.git: master at 8b3f1a2, locked "));
+    assert!(stdout.contains("master at 1234567, locked "));
+    assert!(stdout.contains("ago, via git@github.com:org/producer.git"));
+    assert!(stdout.contains("nested/payments: master at abcdef0"));
+    assert!(!stdout.contains("ignored"));
+}
+
+#[test]
+fn test_scan_filters_by_producer_with_json_output() {
+    let dir = tempfile::tempdir().unwrap();
+    write_projects(dir.path());
+
+    let output = command(&dir)
+        .arg("--output=json")
+        .arg("scan")
+        .arg("--producer=https://github.com/org/Producer.git")
+        .arg(".")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let consumers = result["consumers"].as_array().unwrap();
+    assert_eq!(1, consumers.len());
+    assert_eq!("git@github.com:org/orders.git", consumers[0]["project"]);
+    assert_eq!(
+        "8b3f1a2c4d5e6f708192a3b4c5d6e7f809102030",
+        consumers[0]["commit"]
+    );
+    assert_eq!(serde_json::Value::Null, consumers[0]["commits_behind"]);
+}
+
+#[test]
+fn test_scan_fetch_counts_commits_behind_on_each_branch_of_a_repo() {
+    let dir = tempfile::tempdir().unwrap();
+    let upstream = dir.path().join("upstream");
+    fs::create_dir_all(&upstream).unwrap();
+    git(&upstream, &["init", "-b", "master"]);
+    let base = commit(&upstream, "first");
+    git(&upstream, &["branch", "develop"]);
+    commit(&upstream, "second");
+    git(&upstream, &["checkout", "develop"]);
+    commit(&upstream, "third");
+    commit(&upstream, "fourth");
+
+    let url = "https://example.com/org/producer.git";
+    for (project, branch) in &[("orders", "master"), ("payments", "develop")] {
+        write_project(
+            &dir.path().join("projects").join(project),
+            &format!(
+                "min_protovend_version: 0.0.0\nvendor:\n  - url: {}\n    branch: {}\n",
+                url, branch
+            ),
+            &format!(
+                "imports:\n  - branch: {}\n    commit: {}\n    url: {}\nmin_protovend_version: 0.0.0\nupdated: \"2019-11-20 15:02:12.330896\"\n",
+                branch, base, url
+            ),
+        );
+    }
+
+    let cache = tempfile::tempdir().unwrap();
+    let output = command_with_upstream(&dir, url, &upstream)
+        .env("TMPDIR", cache.path())
+        .env("PROTOVEND_LOCK_TIMEOUT", "5")
+        .arg("scan")
+        .arg("--fetch")
+        .arg("projects")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("Cannot fetch"));
+    assert!(stdout.contains("orders: master at"));
+    assert!(stdout.contains(", 1 commit(s) behind"));
+    assert!(stdout.contains("payments: develop at"));
+    assert!(stdout.contains(", 2 commit(s) behind"));
+}
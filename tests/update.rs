@@ -14,7 +14,7 @@
  * limitations under the License.
 */
 
-use common::command;
+use common::{command, command_with_upstream, git};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use tempfile;
@@ -124,3 +124,119 @@ updated: 2017-08-14 17:15:13.549503",
         .join("./vendor/proto/skyscanner/protovendtestprotos/heartbeat-v2.proto")
         .exists());
 }
+
+#[test]
+fn test_update_dry_run_does_not_write_lock() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let status = command(&dir).arg("init").status().unwrap();
+    assert!(status.success());
+    let lock_before = fs::read_to_string(dir.path().join(".protovend.lock")).unwrap();
+
+    let output = command(&dir)
+        .arg("update")
+        .arg("--dry-run")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("All dependencies are up to date"));
+    assert_eq!(
+        lock_before,
+        fs::read_to_string(dir.path().join(".protovend.lock")).unwrap()
+    );
+    assert!(!dir.path().join("vendor/proto").exists());
+}
+
+#[test]
+fn test_update_writes_changelog() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let status = command(&dir).arg("init").status().unwrap();
+    assert!(status.success());
+
+    let status = command(&dir)
+        .arg("update")
+        .arg("--dry-run")
+        .arg("--changelog=CHANGES.md")
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    assert_eq!(
+        "## Protobuf dependency changes\n\nNo dependencies changed.\n",
+        fs::read_to_string(dir.path().join("CHANGES.md")).unwrap()
+    );
+}
+
+#[test]
+fn test_update_dry_run_reports_diff_and_changelog_of_locked_dependency() {
+    let upstream = tempfile::tempdir().unwrap();
+    let proto = upstream.path().join("proto/org/producer/a.proto");
+    fs::create_dir_all(proto.parent().unwrap()).unwrap();
+    fs::write(&proto, "syntax = \"proto3\";\nmessage Old {}\n").unwrap();
+    git(&upstream, &["init", "-b", "master"]);
+    git(&upstream, &["add", "."]);
+    git(&upstream, &["commit", "-m", "Add Old"]);
+    let locked = git(&upstream, &["rev-parse", "HEAD"]);
+
+    let url = "https://example.com/org/producer.git";
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join(".protovend.yml"),
+        format!(
+            "min_protovend_version: 0.0.0\nvendor:\n  - url: {}\n    branch: master\n",
+            url
+        ),
+    )
+    .unwrap();
+    let cache = tempfile::tempdir().unwrap();
+    let status = command_with_upstream(&dir, url, upstream.path())
+        .env("TMPDIR", cache.path())
+        .arg("install")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let lock_before = fs::read_to_string(dir.path().join(".protovend.lock")).unwrap();
+    assert!(lock_before.contains(&locked));
+
+    fs::write(
+        &proto,
+        "syntax = \"proto3\";\nmessage Old {}\nmessage New {}\n",
+    )
+    .unwrap();
+    git(&upstream, &["commit", "-am", "Add New"]);
+    let latest = git(&upstream, &["rev-parse", "HEAD"]);
+
+    let output = command_with_upstream(&dir, url, upstream.path())
+        .env("TMPDIR", cache.path())
+        .arg("update")
+        .arg("--dry-run")
+        .arg("--changelog=CHANGES.md")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("{} would move from {} to {}", url, locked, latest)));
+    assert!(stdout.contains("+++ b/vendor/proto/org/producer/a.proto"));
+    assert!(stdout.contains("+message New {}"));
+
+    let changelog = fs::read_to_string(dir.path().join("CHANGES.md")).unwrap();
+    assert!(changelog.contains(&format!("`{}` → `{}`", &locked[..7], &latest[..7])));
+    assert!(changelog.contains(&format!(
+        "- [`{}`](https://example.com/org/producer/commit/{}) Add New",
+        &latest[..7],
+        latest
+    )));
+
+    assert_eq!(
+        lock_before,
+        fs::read_to_string(dir.path().join(".protovend.lock")).unwrap()
+    );
+    assert_eq!(
+        "syntax = \"proto3\";\nmessage Old {}\n",
+        fs::read_to_string(dir.path().join("vendor/proto/org/producer/a.proto")).unwrap()
+    );
+}